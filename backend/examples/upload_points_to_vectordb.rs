@@ -1,9 +1,8 @@
-use backend::vectorization::chunk::{chunk, chunk_by_words};
+use backend::vectorization::chunk::chunk_by_words;
 use backend::vectorization::document::Document;
 use backend::vectorization::embedding::embed_batch;
 use backend::vectorization::utils::compile_vectors;
 use backend::storage::vector::{VectorStorage};
-use fastembed::EmbeddingModel;
 use std::error::Error;
 use std::path::Path;
 use tracing::error;
@@ -29,9 +28,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
     };
 
     let db_collection = "texas_christian_university";
+    let mode = config.retrieval.query_options(db_collection).mode;
 
     // vector_storage
-    //     .create_collection(db_collection, EmbeddingModel::BGELargeENV15)
+    //     .create_collection(db_collection, EmbeddingModel::BGELargeENV15, mode)
     //     .await?;
 
     // bge large
@@ -48,7 +48,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let embeddings = embed_batch(&chunks)?;
 
-    let vectors = compile_vectors(document.file_name, chunks, embeddings)?;
+    let vectors = compile_vectors(document.file_name, chunks, embeddings, mode)?;
 
     vector_storage.add_vectors(db_collection, vectors).await?;
 
@@ -4,6 +4,10 @@ create type chat.message_role as enum ('user', 'assistant');
 
 create type chat.conversation_status as enum ('active', 'archived', 'starred', 'system');
 
+create type chat.feedback_rating as enum ('up', 'down');
+
+create type chat.feedback_category as enum ('incorrect', 'outdated', 'unhelpful', 'unsafe');
+
 create table chat.users
 (
     id               serial
@@ -18,7 +22,9 @@ create table chat.users
     first_name       varchar(100),
     last_name        varchar(100),
     university       varchar(255),
-    academic_profile text
+    academic_profile text,
+    -- grants access to the /admin routes, set by hand
+    is_admin         boolean                  default false             not null
 );
 
 create table chat.conversations
@@ -28,8 +34,14 @@ create table chat.conversations
     owner_id        integer                                                             not null
         references chat.users,
     title           varchar(255)                                                        not null,
+    -- set once the server generated the title, cleared when the owner renames the conversation
+    title_generated boolean                  default false                              not null,
     last_message_at timestamp with time zone default CURRENT_TIMESTAMP                  not null,
-    status          chat.conversation_status default 'active'::chat.conversation_status not null
+    status          chat.conversation_status default 'active'::chat.conversation_status not null,
+    -- set when the owner deletes the conversation, rows are removed by the retention job later
+    deleted_at      timestamp with time zone,
+    -- last message of the branch shown to the user and used as completion context
+    active_leaf_id  integer
 );
 
 create table chat.messages
@@ -38,11 +50,82 @@ create table chat.messages
         primary key,
     conversation_id integer                                            not null
         references chat.conversations,
+    -- previous message in the branch, null for the first message
+    parent_id       integer
+        references chat.messages,
     content         text                                               not null,
     role            chat.message_role                                  not null,
-    created_at      timestamp with time zone default CURRENT_TIMESTAMP not null
+    created_at      timestamp with time zone default CURRENT_TIMESTAMP not null,
+    -- how an assistant message was generated, null on user messages
+    model             varchar(255),
+    prompt_tokens     integer,
+    completion_tokens integer,
+    -- milliseconds from receiving the question until the answer was complete
+    latency_ms        integer,
+    -- see llm::prompt::Instruction::template_version
+    template_version  varchar(50),
+    -- the sources placed in the prompt, a JSON array of rag::source::Source
+    sources           jsonb,
+    -- what the vector storage was searched with, the question rewritten by the LLM if enabled
+    search_queries    text[],
+    -- content embedding for semantic search, filled in by the embeddings job
+    embedding         real[]
+);
+
+create table chat.message_feedback
+(
+    id         serial
+        primary key,
+    message_id integer                                            not null
+        references chat.messages on delete cascade,
+    user_id    integer                                            not null
+        references chat.users,
+    rating     chat.feedback_rating                               not null,
+    category   chat.feedback_category,
+    comment    text,
+    created_at timestamp with time zone default CURRENT_TIMESTAMP not null,
+    updated_at timestamp with time zone default CURRENT_TIMESTAMP not null,
+    -- a user has one rating per message, rating again replaces it
+    unique (message_id, user_id)
+);
+
+
+create table chat.conversation_shares
+(
+    id               serial
+        primary key,
+    conversation_id  integer                                            not null
+        references chat.conversations on delete cascade,
+    -- secret part of the share link
+    token            varchar(64)                                        not null
+        unique,
+    -- last message shown, later messages are not part of the shared snapshot
+    snapshot_leaf_id integer                                            not null
+        references chat.messages on delete cascade,
+    -- only authenticated users of the owner's university may view the share
+    same_university  boolean                  default false             not null,
+    created_at       timestamp with time zone default CURRENT_TIMESTAMP not null,
+    expires_at       timestamp with time zone                           not null,
+    revoked_at       timestamp with time zone
+);
+
+create table chat.conversation_summaries
+(
+    id                 serial
+        primary key,
+    conversation_id    integer                                            not null
+        references chat.conversations on delete cascade,
+    -- last message covered, the summary stands for the branch up to and including it
+    through_message_id integer                                            not null
+        unique
+        references chat.messages on delete cascade,
+    content            text                                               not null,
+    created_at         timestamp with time zone default CURRENT_TIMESTAMP not null
 );
 
+alter table chat.conversations
+    add foreign key (active_leaf_id) references chat.messages on delete set null;
+
 
 -- User lookup indices
 CREATE INDEX idx_users_email ON chat.users(email);
@@ -53,6 +136,8 @@ CREATE INDEX idx_conversations_owner_id ON chat.conversations(owner_id);
 CREATE INDEX idx_conversations_last_message_at ON chat.conversations(last_message_at);
 CREATE INDEX idx_conversations_status ON chat.conversations(status);
 
+CREATE INDEX idx_conversations_deleted_at ON chat.conversations(deleted_at) WHERE deleted_at IS NOT NULL;
+
 -- Composite index for filtered conversation queries
 CREATE INDEX idx_conversations_owner_status ON chat.conversations(owner_id, status);
 
@@ -60,6 +145,17 @@ CREATE INDEX idx_conversations_owner_status ON chat.conversations(owner_id, stat
 CREATE INDEX idx_messages_conversation_id ON chat.messages(conversation_id);
 CREATE INDEX idx_messages_role ON chat.messages(role);
 CREATE INDEX idx_messages_created_at ON chat.messages(created_at);
+CREATE INDEX idx_messages_parent_id ON chat.messages(parent_id);
+CREATE INDEX idx_messages_unembedded ON chat.messages(id) WHERE embedding IS NULL;
+
+-- Full-text search over message content
+CREATE INDEX idx_messages_content_fts ON chat.messages USING gin (to_tsvector('english', content));
 
 -- Composite index for message retrieval in chronological order
-CREATE INDEX idx_messages_conversation_created ON chat.messages(conversation_id, created_at);
\ No newline at end of file
+CREATE INDEX idx_messages_conversation_created ON chat.messages(conversation_id, created_at);
+
+-- Share lookup indices
+CREATE INDEX idx_conversation_shares_conversation_id ON chat.conversation_shares(conversation_id);
+
+-- Feedback lookup indices
+CREATE INDEX idx_message_feedback_created_at ON chat.message_feedback(created_at);
@@ -0,0 +1,136 @@
+-- Brings a database created from an older creation_script.sql up to date, safe to run repeatedly.
+
+-- soft delete for conversations
+alter table chat.conversations
+    add column if not exists deleted_at timestamp with time zone;
+
+CREATE INDEX IF NOT EXISTS idx_conversations_deleted_at ON chat.conversations(deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- message tree, existing messages are linked into a single branch in creation order
+-- which becomes the active branch
+
+alter table chat.messages
+    add column if not exists parent_id integer references chat.messages;
+
+alter table chat.conversations
+    add column if not exists active_leaf_id integer references chat.messages on delete set null;
+
+CREATE INDEX IF NOT EXISTS idx_messages_parent_id ON chat.messages(parent_id);
+
+update chat.messages m
+set parent_id = ordered.previous_id
+from (select id,
+             lag(id) over (partition by conversation_id order by created_at, id) as previous_id
+      from chat.messages) ordered
+where m.id = ordered.id
+  and m.parent_id is null;
+
+update chat.conversations c
+set active_leaf_id = (select id
+                      from chat.messages
+                      where conversation_id = c.id
+                      order by created_at desc, id desc
+                      limit 1)
+where c.active_leaf_id is null;
+
+-- message feedback, admins and generation details of assistant messages
+
+DO $$
+BEGIN
+    CREATE TYPE chat.feedback_rating AS ENUM ('up', 'down');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
+
+DO $$
+BEGIN
+    CREATE TYPE chat.feedback_category AS ENUM ('incorrect', 'outdated', 'unhelpful', 'unsafe');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
+
+alter table chat.users
+    add column if not exists is_admin boolean default false not null;
+
+alter table chat.messages
+    add column if not exists model varchar(255),
+    add column if not exists sources jsonb;
+
+create table if not exists chat.message_feedback
+(
+    id         serial
+        primary key,
+    message_id integer                                            not null
+        references chat.messages on delete cascade,
+    user_id    integer                                            not null
+        references chat.users,
+    rating     chat.feedback_rating                               not null,
+    category   chat.feedback_category,
+    comment    text,
+    created_at timestamp with time zone default CURRENT_TIMESTAMP not null,
+    updated_at timestamp with time zone default CURRENT_TIMESTAMP not null,
+    unique (message_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_feedback_created_at ON chat.message_feedback(created_at);
+
+-- generation details of assistant messages
+
+alter table chat.messages
+    add column if not exists prompt_tokens integer,
+    add column if not exists completion_tokens integer,
+    add column if not exists latency_ms integer,
+    add column if not exists template_version varchar(50);
+
+-- conversation search
+
+alter table chat.messages
+    add column if not exists embedding real[];
+
+CREATE INDEX IF NOT EXISTS idx_messages_unembedded ON chat.messages(id) WHERE embedding IS NULL;
+CREATE INDEX IF NOT EXISTS idx_messages_content_fts ON chat.messages USING gin (to_tsvector('english', content));
+
+-- share links
+
+create table if not exists chat.conversation_shares
+(
+    id               serial
+        primary key,
+    conversation_id  integer                                            not null
+        references chat.conversations on delete cascade,
+    token            varchar(64)                                        not null
+        unique,
+    snapshot_leaf_id integer                                            not null
+        references chat.messages on delete cascade,
+    same_university  boolean                  default false             not null,
+    created_at       timestamp with time zone default CURRENT_TIMESTAMP not null,
+    expires_at       timestamp with time zone                           not null,
+    revoked_at       timestamp with time zone
+);
+
+CREATE INDEX IF NOT EXISTS idx_conversation_shares_conversation_id ON chat.conversation_shares(conversation_id);
+
+-- server-side conversation titles
+
+alter table chat.conversations
+    add column if not exists title_generated boolean default false not null;
+
+-- summaries of long conversation histories
+
+create table if not exists chat.conversation_summaries
+(
+    id                 serial
+        primary key,
+    conversation_id    integer                                            not null
+        references chat.conversations on delete cascade,
+    through_message_id integer                                            not null
+        unique
+        references chat.messages on delete cascade,
+    content            text                                               not null,
+    created_at         timestamp with time zone default CURRENT_TIMESTAMP not null
+);
+
+-- rewritten search queries of answers
+
+alter table chat.messages
+    add column if not exists search_queries text[];
@@ -2,6 +2,13 @@ pub mod auth;
 pub mod router;
 pub mod health;
 pub mod completions;
+pub mod error;
 mod jwt;
+mod ownership;
+mod shares;
+mod sse;
+#[cfg(test)]
+pub(crate) mod testing;
 mod conversations;
+mod feedback;
 mod users;
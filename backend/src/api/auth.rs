@@ -1,3 +1,4 @@
+use crate::api::error::AppError;
 use crate::api::jwt::TokenClaims;
 use crate::app_state::AppState;
 use argon2::{
@@ -5,7 +6,7 @@ use argon2::{
     Argon2,
 };
 use axum::extract::State;
-use axum::http::{header, HeaderValue, Response, StatusCode};
+use axum::http::{header, HeaderValue, Response};
 use axum::response::IntoResponse;
 use axum::Json;
 use axum_extra::extract::cookie::{Cookie, SameSite};
@@ -13,7 +14,7 @@ use jsonwebtoken::{encode, EncodingKey, Header};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::sync::Arc;
-use tracing::{error, info};
+use tracing::info;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateUserSchema {
@@ -33,7 +34,7 @@ fn create_auth_response(
     jwt_secret: &[u8],
     jwt_expired_in: i64,
     jwt_max_age: i64,
-) -> Result<Response<String>, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<Response<String>, AppError> {
     let now = chrono::Utc::now();
     let issued_at = now.timestamp() as usize;
     let expire_at = (now + chrono::Duration::days(jwt_expired_in)).timestamp() as usize;
@@ -44,20 +45,13 @@ fn create_auth_response(
         exp: expire_at,
     };
 
-    let token = match encode(
+    // failing to sign is a server problem, not an invalid token
+    let token = encode(
         &Header::default(),
         &claims,
         &EncodingKey::from_secret(jwt_secret),
-    ) {
-        Ok(token) => token,
-        Err(e) => {
-            error!("{}", e.to_string());
-            let error_response = json!({
-                "message": "Authentication failed due to server error",
-            });
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
-        }
-    };
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to encode JWT: {}", e)))?;
 
     let cookie = Cookie::build(("auth_token", token.to_owned()))
         .path("/")
@@ -67,142 +61,76 @@ fn create_auth_response(
 
     let mut response = Response::new(json!({"auth_token": token}).to_string());
 
-    match cookie.to_string().parse::<HeaderValue>() {
-        Ok(cookie_header_value) => {
-            response
-                .headers_mut()
-                .insert(header::SET_COOKIE, cookie_header_value);
-            Ok(response)
-        }
-        Err(e) => {
-            error!("Failed to parse cookie to header value: {}", e);
-            let error_response = json!({
-                "message": "Authentication failed due to server error",
-            });
-            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)))
-        }
-    }
+    let cookie_header_value = cookie.to_string().parse::<HeaderValue>().map_err(|e| {
+        AppError::Internal(format!("Failed to parse cookie to header value: {}", e))
+    })?;
+
+    response
+        .headers_mut()
+        .insert(header::SET_COOKIE, cookie_header_value);
+    Ok(response)
 }
 
 // POST /api/auth/signup
 pub async fn auth_signup_handler(
     State(state): State<Arc<AppState>>,
     Json(payload): Json<CreateUserSchema>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<impl IntoResponse, AppError> {
     // make sure email is not already taken
-    match state
+    if state
         .relational_storage
         .get_user_by_email(&payload.email)
-        .await
+        .await?
+        .is_some()
     {
-        Err(e) => {
-            // SQL error, failed to execute SQL SELECT
-            error!("Failed on SQL fetch: {}", e.to_string());
-            let error_response = json!({
-                "message": "Unable to signup to a server error",
-            });
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
-        }
-        Ok(Some(_)) => {
-            // User does not exist with email
-            info!("User already exists with email: {}", payload.email);
-            let error_response = json!({
-                "message": "User already exists with email",
-            });
-            return Err((StatusCode::CONFLICT, Json(error_response)));
-        }
-        _ => {}
-    };
+        info!("User already exists with email: {}", payload.email);
+        return Err(AppError::Conflict("User already exists with email".to_string()));
+    }
 
     let salt = SaltString::generate(&mut OsRng);
     let argon2 = Argon2::default();
 
-    let password_hash = match argon2.hash_password(payload.password.as_bytes(), &salt) {
-        Ok(hash) => hash.to_string(),
-        Err(e) => {
-            // failed to hash password
-            error!("{}", e.to_string());
-            let error_response = json!({
-                "message": "Failed to create user due to a server error",
-            });
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
-        }
-    };
+    let password_hash = argon2
+        .hash_password(payload.password.as_bytes(), &salt)
+        .map_err(|e| AppError::Internal(format!("Failed to hash password: {}", e)))?
+        .to_string();
 
-    match state
+    let user = state
         .relational_storage
         .create_user(&payload.email, &password_hash)
-        .await
-    {
-        Ok(user) => {
-            // Generate JWT and create auth response
-            create_auth_response(user.id, state.config.jwt_secret.as_ref(),
-                                 state.config.jwt_expired_in, state.config.jwt_max_age)
-        }
-        Err(e) => {
-            // SQL error, failed to execute SQL SELECT
-            error!("{}", e.to_string());
-            let error_response = json!({
-                "message": "Failed to create user due to a server error",
-            });
-
-            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)))
-        }
-    }
+        .await?;
+
+    // Generate JWT and create auth response
+    create_auth_response(user.id, state.config.jwt_secret.as_ref(),
+                         state.config.jwt_expired_in, state.config.jwt_max_age)
 }
 
 // POST /api/auth/login
 pub async fn auth_login_handler(
     State(state): State<Arc<AppState>>,
     Json(payload): Json<LoginUserSchema>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<impl IntoResponse, AppError> {
     // Check if user exists with email
-    let user = match state
+    let user = state
         .relational_storage
         .get_user_by_email(&payload.email)
-        .await
-    {
-        Err(e) => {
-            // SQL error, failed to execute SQL SELECT
-            error!("Failed on SQL fetch: {}", e.to_string());
-            let error_response = json!({
-                "message": "Unable to login due to a server error",
-            });
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
-        }
-        Ok(None) => {
-            // User does not exist with email
+        .await?
+        .ok_or_else(|| {
             info!("User does not exist with email: {}", payload.email);
-            let error_response = json!({
-                "message": "Incorrect email or password",
-            });
-            return Err((StatusCode::UNAUTHORIZED, Json(error_response)));
-        }
-        Ok(Some(user)) => user,
-    };
+            AppError::InvalidCredentials
+        })?;
 
-    let parsed_hash = match PasswordHash::new(&user.password_hash) {
-        Err(e) => {
-            error!("Failed on password hash: {}", e.to_string());
-            let error_response = json!({
-                "message": "Unable to login due to a server error",
-            });
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
-        }
-        Ok(parsed_hash) => parsed_hash,
-    };
+    let parsed_hash = PasswordHash::new(&user.password_hash)
+        .map_err(|e| AppError::Internal(format!("Failed on password hash: {}", e)))?;
 
     // Verify password
     let password_correct = Argon2::default()
-        .verify_password(&payload.password.as_bytes(), &parsed_hash)
+        .verify_password(payload.password.as_bytes(), &parsed_hash)
         .is_ok();
 
     if !password_correct {
         info!("Incorrect password");
-        let error_response = json!({
-            "message": "Incorrect email or password",
-        });
-        return Err((StatusCode::UNAUTHORIZED, Json(error_response)));
+        return Err(AppError::InvalidCredentials);
     }
 
     // Generate JWT and create auth response
@@ -211,7 +139,7 @@ pub async fn auth_login_handler(
 }
 
 // GET /api/auth/logout
-pub async fn auth_logout_handler() -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+pub async fn auth_logout_handler() -> Result<impl IntoResponse, AppError> {
     // make new cookie to invalid current cookie in the browser
     let cookie = Cookie::build(("auth_token", ""))
         .path("/")
@@ -219,10 +147,14 @@ pub async fn auth_logout_handler() -> Result<impl IntoResponse, (StatusCode, Jso
         .same_site(SameSite::Lax)
         .http_only(true);
 
+    let cookie_header_value = cookie.to_string().parse::<HeaderValue>().map_err(|e| {
+        AppError::Internal(format!("Failed to parse cookie to header value: {}", e))
+    })?;
+
     let mut response = Response::new(json!({}).to_string());
     response
         .headers_mut()
-        .insert(header::SET_COOKIE, cookie.to_string().parse().unwrap());
+        .insert(header::SET_COOKIE, cookie_header_value);
     Ok(response)
 }
 
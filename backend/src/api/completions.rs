@@ -1,35 +1,60 @@
 use std::convert::Infallible;
+use crate::api::error::AppError;
+use crate::api::ownership::{OwnedConversation, OwnedMessage};
+use crate::api::sse::{completion_events, into_sse_events, CompletionErrorCode, CompletionEvent};
 use crate::app_state::AppState;
+use crate::jobs::titles;
+use crate::llm::budget::PromptBudget;
 use crate::llm::inference::InferenceRequest;
 use crate::llm::prompt::{Instruction, Prompt};
-use crate::vectorization::embedding::embed;
-use crate::storage::model::{DBMessageRole, DBUser};
-use crate::storage::vector::VectorStorage;
+use crate::rag::history::HistoryManager;
+use crate::rag::pipeline::RagPipeline;
+use crate::rag::source::Source;
+use crate::storage::model::{DBGeneration, DBMessage, DBMessageRole, DBUser};
+use crate::storage::postgres::RelationalStorage;
 use axum::extract::State;
-use axum::http::StatusCode;
 use axum::response::{IntoResponse, Sse};
 use axum::{Extension, Json};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::sync::Arc;
+use std::time::Instant;
 use axum::response::sse::Event;
 use futures_util::{stream, Stream, StreamExt};
-use ollama_rs::generation::completion::request::GenerationRequest;
-use ollama_rs::generation::options::GenerationOptions;
 use tracing::{error, info};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ApiMessage {
     pub role: DBMessageRole,
     pub content: String,
 }
 
+impl From<DBMessage> for ApiMessage {
+    fn from(message: DBMessage) -> Self {
+        Self {
+            role: message.role,
+            content: message.content,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateCompletionSchema {
     pub messages: Vec<ApiMessage>,
     pub collection: String,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateConversationCompletionSchema {
+    pub content: String,
+    pub collection: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RegenerateCompletionSchema {
+    pub collection: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateTitleCompletionSchema {
     pub messages: Vec<ApiMessage>,
@@ -38,249 +63,320 @@ pub struct CreateTitleCompletionSchema {
 // TODO: why not grab collection from user profile instead of passing it via JSON?
 
 /// POST /api/completions -> JWT required
-// TODO: error handling is horrible in this method, please fix.
-// TODO: generalize error message return (look at auth for this)
 pub async fn completion_new_handler(
     State(state): State<Arc<AppState>>,
     Extension(user): Extension<DBUser>,
     Json(payload): Json<CreateCompletionSchema>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let user_queries: Vec<String> = payload
-        .messages
-        .iter()
-        .filter(|message| matches!(message.role, DBMessageRole::User))
-        .map(|message| message.content.clone())
-        .collect();
-
-    let user_queries_flattened = user_queries.join(" ");
-
-    let user_queries_embedded = embed(user_queries_flattened).unwrap();
-
-    let vector_search_result = match state
-        .vector_storage
-        .query(&payload.collection, user_queries_embedded)
-        .await {
-        Ok(result) => result,
-        Err(e) => {
-            error!("Failed on vector search: {}", e.to_string());
-            let error_response = json!({
-                "message": "Unable to create completion",
-            });
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
-        }
-    };
-
-    // TODO: look into the score of what is returned...
-    // add five highest rated results from VecDB to context for query
-    let mut context = String::new();
-    vector_search_result
-        .points
-        .into_iter()
-        .take(5)
-        .for_each(|point| context.push_str(&point.content));
-
-    let profile = user.academic_profile.unwrap_or_else(|| "".to_string());
-
-    let prompt = Prompt::new(
-        payload.messages,
-        Some(profile),
-        Some(context),
-        Some(user_queries.last().unwrap().to_string()),
-        Instruction::RAG,
-    );
+) -> Result<impl IntoResponse, AppError> {
+    let pipeline = rag_pipeline(&state, &payload.collection);
 
-    // info!("PROMPT:\n\n{:?}\n\n", prompt);
-
-    let completion = state
-        .llm
-        .generate(InferenceRequest::new(prompt))
-        .await
-        .unwrap();
+    let completion = pipeline
+        .complete(payload.messages, user.academic_profile)
+        .await?;
 
     let json_response = json!({
-        "content": completion.content,
-        "generation_time": completion.generation_time,
-        "token_count": completion.token_count
+        "content": completion.response.content,
+        "generation_time": completion.response.generation_time,
+        "token_count": completion.response.token_count,
+        "sources": completion.sources,
+        "search_queries": completion.search_queries,
+        "timings": completion.timings
     });
 
     Ok(Json(json_response))
 }
 
-/// GET /api/completions/stream -> JWT required
+/// POST /api/completions/stream -> JWT required
+/// Responds with server-sent events, see api::sse::CompletionEvent for the protocol
 pub async fn completion_streaming_handler(
     State(state): State<Arc<AppState>>,
     Extension(user): Extension<DBUser>,
     Json(payload): Json<CreateCompletionSchema>,
-) -> Result<Sse<impl Stream<Item=Result<Event, Infallible>>>, (StatusCode, Json<serde_json::Value>)> {
-    let user_queries: Vec<String> = payload
-        .messages
-        .iter()
-        .filter(|message| matches!(message.role, DBMessageRole::User))
-        .map(|message| message.content.clone())
-        .collect();
-
-    let user_queries_flattened = user_queries.join(" ");
-
-    let user_queries_embedded = match embed(user_queries_flattened) {
-        Ok(embedded) => embedded,
-        Err(e) => {
-            error!("Failed to embed user queries: {}", e.to_string());
-            let error_response = json!({
-                "message": "Unable to process completion request",
-            });
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
-        }
-    };
+) -> Result<Sse<impl Stream<Item=Result<Event, Infallible>>>, AppError> {
+    let pipeline = rag_pipeline(&state, &payload.collection);
 
-    let vector_search_result = match state
-        .vector_storage
-        .query(&payload.collection, user_queries_embedded)
-        .await {
-        Ok(result) => result,
-        Err(e) => {
-            error!("Failed on vector search: {}", e.to_string());
-            let error_response = json!({
-                "message": "Unable to create completion",
-            });
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
-        }
-    };
+    let rag_stream = pipeline
+        .complete_stream(payload.messages, user.academic_profile)
+        .await?;
 
-    let mut context = String::new();
-    vector_search_result
-        .points
-        .into_iter()
-        .take(5)
-        .for_each(|point| context.push_str(&point.content));
-
-    let profile = user.academic_profile.unwrap();
-
-    let profile_embedded = match embed(profile.clone()) {
-        Ok(embedded) => embedded,
-        Err(e) => {
-            error!("Failed to embed user academic profile: {}", e.to_string());
-            let error_response = json!({
-                "message": "Unable to process completion request",
-            });
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
-        }
-    };
+    let model = state.llm.model().to_string();
+    let events = completion_events(rag_stream.sources, rag_stream.stream, model);
+
+    Ok(Sse::new(into_sse_events(events)))
+}
 
-    let profile_vector_search_result = match state
-        .vector_storage
-        .query(&payload.collection, profile_embedded)
-        .await {
-        Ok(result) => result,
-        Err(e) => {
-            error!("Failed on vector search: {}", e.to_string());
-            let error_response = json!({
-                "message": "Unable to create completion",
-            });
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
+/// POST /api/conversations/{conversation_id}/completions -> JWT required
+/// Stores a new user message and answers it using the stored conversation history. The message
+/// and its answer only become the active branch once the answer is stored, a failed or
+/// interrupted completion keeps the message off the active branch. Responds with server-sent
+/// events like /completions/stream.
+pub async fn completion_conversation_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<DBUser>,
+    OwnedConversation(conversation): OwnedConversation,
+    Json(payload): Json<CreateConversationCompletionSchema>,
+) -> Result<Sse<impl Stream<Item=Result<Event, Infallible>>>, AppError> {
+    if payload.content.trim().is_empty() {
+        return Err(AppError::InvalidRequest(
+            "Message content must not be empty".to_string(),
+        ));
+    }
+
+    // history comes from storage, the client only sends the new message
+    let parent_id = state
+        .relational_storage
+        .get_active_leaf_id(conversation.id)
+        .await?;
+    let question = state
+        .relational_storage
+        .create_question(conversation.id, parent_id, payload.content)
+        .await?;
+
+    answer_branch(state, user, conversation.id, question.id, &payload.collection).await
+}
+
+/// POST /api/conversations/{conversation_id}/messages/{message_id}/regenerate -> JWT required
+/// Answers the question of an assistant message again, the new answer becomes a sibling of the
+/// old one and the active branch, unless another branch was selected while it was generated.
+/// Responds like /conversations/{conversation_id}/completions.
+pub async fn completion_regenerate_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<DBUser>,
+    OwnedMessage(conversation, message): OwnedMessage,
+    Json(payload): Json<RegenerateCompletionSchema>,
+) -> Result<Sse<impl Stream<Item=Result<Event, Infallible>>>, AppError> {
+    let question_id = match (&message.role, message.parent_id) {
+        (DBMessageRole::Assistant, Some(parent_id)) => parent_id,
+        _ => {
+            return Err(AppError::InvalidRequest(
+                "Only answers to a user message can be regenerated".to_string(),
+            ))
         }
     };
 
-    profile_vector_search_result
-        .points
-        .into_iter()
-        .take(5)
-        .for_each(|point| context.push_str(&point.content));
+    answer_branch(state, user, conversation.id, question_id, &payload.collection).await
+}
+
+/// POST /api/conversations/{conversation_id}/messages/{message_id}/edit -> JWT required
+/// Stores the edited text as a sibling of a user message and answers it. The original message and
+/// its replies are kept on their own branch.
+/// Responds like /conversations/{conversation_id}/completions.
+pub async fn completion_edit_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<DBUser>,
+    OwnedMessage(conversation, message): OwnedMessage,
+    Json(payload): Json<CreateConversationCompletionSchema>,
+) -> Result<Sse<impl Stream<Item=Result<Event, Infallible>>>, AppError> {
+    if !matches!(message.role, DBMessageRole::User) {
+        return Err(AppError::InvalidRequest(
+            "Only user messages can be edited".to_string(),
+        ));
+    }
+
+    if payload.content.trim().is_empty() {
+        return Err(AppError::InvalidRequest(
+            "Message content must not be empty".to_string(),
+        ));
+    }
+
+    let question = state
+        .relational_storage
+        .create_question(conversation.id, message.parent_id, payload.content)
+        .await?;
+
+    answer_branch(state, user, conversation.id, question.id, &payload.collection).await
+}
 
-    // info!("Total context: {:?}", context);
+// Answers `question` with the branch leading to it as history and stores the answer as its reply
+async fn answer_branch(
+    state: Arc<AppState>,
+    user: DBUser,
+    conversation_id: i32,
+    question_id: i32,
+    collection: &str,
+) -> Result<Sse<impl Stream<Item=Result<Event, Infallible>>>, AppError> {
+    let started = Instant::now();
+    // the answer only becomes the active leaf if no other branch is selected in the meantime
+    let active_leaf_id = state
+        .relational_storage
+        .get_active_leaf_id(conversation_id)
+        .await?;
+    let branch = state.relational_storage.get_branch(question_id).await?;
+    if branch.is_empty() {
+        return Err(AppError::NotFound(format!(
+            "Message not found: {}",
+            question_id
+        )));
+    }
 
-    let prompt = Prompt::new(
-        payload.messages,
-        Some(profile),
-        Some(context),
-        Some(user_queries.last().unwrap().to_string()),
-        Instruction::RAG,
+    // older messages are summarized once the branch outgrows the history budget
+    let history = HistoryManager::new(
+        &state.relational_storage,
+        state.llm.as_ref(),
+        prompt_budget(&state),
+    )
+    .fit(branch)
+    .await?;
+
+    let rag_stream = rag_pipeline(&state, collection)
+        .complete_stream(history, user.academic_profile)
+        .await?;
+
+    info!(
+        "Searched {} for conversation {} with {:?}",
+        collection, conversation_id, rag_stream.search_queries
     );
 
-    // Convert our prompt to the format needed for ollama
-    let model_name = state.llm.model().to_string();
-    let prompt_str = match prompt.instruction {
-        Instruction::RAG => prompt.to_string_rag(),
-        Instruction::Title => prompt.to_string_title(),
+    let model = state.llm.model().to_string();
+    let answer = PendingAnswer {
+        conversation_id,
+        question_id,
+        active_leaf_id,
+        sources: rag_stream.sources.clone(),
+        search_queries: rag_stream.search_queries,
+        template_version: Instruction::RAG.template_version(),
+        started,
     };
+    let events = completion_events(rag_stream.sources, rag_stream.stream, model);
+    let events = store_answer(events, state.clone(), answer);
+
+    Ok(Sse::new(into_sse_events(events)))
+}
+
+fn prompt_budget(state: &AppState) -> PromptBudget {
+    PromptBudget::new(
+        state.llm.model(),
+        state.config.llm_context_window,
+        state.config.llm_completion_tokens,
+    )
+}
+
+fn rag_pipeline<'a>(state: &'a AppState, collection: &str) -> RagPipeline<'a> {
+    RagPipeline::new(state.vector_storage.as_ref(), state.llm.as_ref(), collection)
+        .embedder(state.embedder)
+        .query_options(state.config.retrieval.query_options(collection))
+        .rerank(state.config.retrieval.rerank.clone())
+        .mmr(state.config.retrieval.mmr.clone())
+        .rewrite(state.config.retrieval.rewrite_queries)
+        .budget(prompt_budget(state))
+}
+
+// The question an answer being streamed replies to and what is known about its generation
+// before the answer is complete
+struct PendingAnswer {
+    conversation_id: i32,
+    question_id: i32,
+    // the active leaf when the question was asked
+    active_leaf_id: Option<i32>,
+    sources: Vec<Source>,
+    search_queries: Vec<String>,
+    template_version: &'static str,
+    started: Instant,
+}
+
+impl PendingAnswer {
+    fn generation(
+        &self,
+        model: &str,
+        token_count: Option<u32>,
+        prompt_token_count: Option<u32>,
+    ) -> DBGeneration {
+        DBGeneration {
+            model: model.to_string(),
+            prompt_tokens: prompt_token_count.and_then(|count| i32::try_from(count).ok()),
+            completion_tokens: token_count.and_then(|count| i32::try_from(count).ok()),
+            latency_ms: i32::try_from(self.started.elapsed().as_millis()).ok(),
+            template_version: self.template_version.to_string(),
+            sources: self.sources.clone(),
+            search_queries: self.search_queries.clone(),
+        }
+    }
+
+    async fn store(
+        &self,
+        storage: &RelationalStorage,
+        answer: String,
+        generation: &DBGeneration,
+    ) -> Result<DBMessage, sqlx::Error> {
+        storage
+            .create_answer(
+                self.conversation_id,
+                self.question_id,
+                answer,
+                generation,
+                self.active_leaf_id,
+            )
+            .await
+    }
+}
 
-    let options = GenerationOptions::default()
-        .temperature(0.2)
-        // .repeat_penalty(1.3)
-        // .top_k(40)
-        // .top_p(0.75)
-        .num_ctx(32000);
-
-    // Create the stream
-    let stream_result = state.llm.client.generate_stream(
-        GenerationRequest::new(model_name, prompt_str).options(options)
-    ).await;
-
-    match stream_result {
-        Ok(ollama_stream) => {
-            // Create a new stream that will transform the Ollama stream into SSE events
-            let event_stream = stream::unfold(ollama_stream, |mut stream| async move {
-                match stream.next().await {
-                    Some(result) => {
-                        match result {
-                            Ok(responses) => {
-                                // Process responses
-                                if responses.is_empty() {
-                                    // Just continue to next chunk if empty
-                                    Some((Ok(Event::default().event("message").data("")), stream))
-                                } else {
-                                    // Process all responses in the chunk
-                                    let combined_response = responses.iter()
-                                        .map(|resp| resp.response.clone())
-                                        .collect::<Vec<String>>()
-                                        .join("");
-
-                                    if !combined_response.is_empty() {
-                                        Some((Ok(Event::default().event("message").data(combined_response)), stream))
-                                    } else {
-                                        // Continue to next chunk
-                                        Some((Ok(Event::default().event("message").data("")), stream))
-                                    }
-                                }
+// Collects the streamed answer and stores it before `done` is sent. No answer is stored when the
+// stream fails or is dropped before it completes, the question stays off the active branch.
+fn store_answer(
+    events: impl Stream<Item=CompletionEvent> + Send + 'static,
+    state: Arc<AppState>,
+    pending: PendingAnswer,
+) -> impl Stream<Item=CompletionEvent> {
+    stream::unfold(
+        (events.boxed(), String::new(), state, pending),
+        |(mut events, mut answer, state, pending)| async move {
+            let event = match events.next().await? {
+                CompletionEvent::Token { content } => {
+                    answer.push_str(&content);
+                    CompletionEvent::Token { content }
+                }
+                CompletionEvent::Done {
+                    model,
+                    token_count,
+                    prompt_token_count,
+                    generation_time,
+                    ..
+                } => {
+                    let generation = pending.generation(&model, token_count, prompt_token_count);
+
+                    match pending
+                        .store(&state.relational_storage, answer.clone(), &generation)
+                        .await
+                    {
+                        Ok(message) => {
+                            // only titles conversations whose first exchange this was
+                            titles::spawn(state.clone(), pending.conversation_id);
+
+                            CompletionEvent::Done {
+                                model,
+                                token_count,
+                                prompt_token_count,
+                                generation_time,
+                                message_id: Some(message.id),
                             }
-                            Err(e) => {
-                                // Handle error in the stream
-                                error!("Error in ollama stream: {}", e);
-                                Some((
-                                    Ok(Event::default()
-                                        .event("error")
-                                        .data(format!("Error: {}", e))),
-                                    stream,
-                                ))
+                        }
+                        Err(e) => {
+                            error!("Failed to store assistant message: {}", e);
+                            CompletionEvent::Error {
+                                code: CompletionErrorCode::PersistenceFailed,
+                                message: "The answer could not be saved to the conversation"
+                                    .to_string(),
                             }
                         }
                     }
-                    None => {
-                        // Stream has ended - we need to return None entirely to end the stream
-                        None
-                    }
                 }
-            });
-            Ok(Sse::new(event_stream))
-        }
-        Err(e) => {
-            error!("Failed to create stream: {}", e.to_string());
-            let error_response = json!({
-                "message": "Unable to create completion stream",
-            });
-            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)))
-        }
-    }
-}
+                event => event,
+            };
 
+            Some((event, (events, answer, state, pending)))
+        },
+    )
+}
 
 /// POST /api/completions/title -> JWT required
-// TODO: using unwrap... server will crash if completion is not executed properly
+/// Kept for older clients, titles are generated by the server after the first exchange now.
 pub async fn completion_new_title_handler(
     State(state): State<Arc<AppState>>,
     Json(payload): Json<CreateTitleCompletionSchema>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<impl IntoResponse, AppError> {
     let prompt = Prompt {
         history: payload.messages,
+        summary: None,
         profile: None,
         context: None,
         question: None,
@@ -290,8 +386,7 @@ pub async fn completion_new_title_handler(
     let completion = state
         .llm
         .generate(InferenceRequest::new(prompt))
-        .await
-        .unwrap();
+        .await?;
 
     let json_response = json!({
         "content": completion.content,
@@ -300,4 +395,307 @@ pub async fn completion_new_title_handler(
     });
 
     Ok(Json(json_response))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::completions::{store_answer, PendingAnswer};
+    use crate::api::sse::CompletionEvent;
+    use crate::api::testing::{request, send, test_app, test_app_with_llm, test_user};
+    use crate::llm::mock::{MockAdapter, MockFailure};
+    use crate::llm::model::Model;
+    use crate::rag::source::Source;
+    use crate::storage::model::DBMessageRole;
+    use axum::http::{Method, StatusCode};
+    use axum::response::Response;
+    use futures_util::{stream, StreamExt};
+    use serde_json::json;
+    use sqlx::PgPool;
+    use std::time::{Duration, Instant};
+
+    async fn event_names(response: Response) -> Vec<String> {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        String::from_utf8_lossy(&bytes)
+            .lines()
+            .filter_map(|line| line.strip_prefix("event: "))
+            .map(str::to_string)
+            .collect()
+    }
+
+    // the question was stored, no answer was, and the active branch is unchanged
+    async fn assert_unanswered(pool: &PgPool, conversation_id: i32, question: &str) {
+        let messages: Vec<(String, DBMessageRole)> =
+            sqlx::query_as("SELECT content, role FROM chat.messages WHERE conversation_id = $1")
+                .bind(conversation_id)
+                .fetch_all(pool)
+                .await
+                .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].0, question);
+        assert!(matches!(messages[0].1, DBMessageRole::User));
+
+        let leaf: Option<i32> =
+            sqlx::query_scalar("SELECT active_leaf_id FROM chat.conversations WHERE id = $1")
+                .bind(conversation_id)
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        assert_eq!(leaf, None);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_answer_keeps_branch_selected_while_streaming(pool: PgPool) {
+        let (_, state) = test_app(pool).await;
+        let (alice, _) = test_user(&state, "alice@tcu.edu").await;
+        let storage = &state.relational_storage;
+        let conversation = storage
+            .create_conversation(alice.id, "Degree plan".to_string())
+            .await
+            .unwrap();
+        let question = storage
+            .create_message(conversation.id, "First?".to_string(), DBMessageRole::User)
+            .await
+            .unwrap();
+
+        let answer = |leaf_id| {
+            let events = stream::iter(vec![
+                CompletionEvent::Token {
+                    content: "MATH 10524".to_string(),
+                },
+                CompletionEvent::Done {
+                    model: "qwen".to_string(),
+                    token_count: Some(2),
+                    prompt_token_count: Some(40),
+                    generation_time: Some(10),
+                    message_id: None,
+                },
+            ]);
+            let pending = PendingAnswer {
+                conversation_id: conversation.id,
+                question_id: question.id,
+                active_leaf_id: leaf_id,
+                sources: vec![],
+                search_queries: vec![],
+                template_version: "rag-1",
+                started: Instant::now(),
+            };
+
+            store_answer(events, state.clone(), pending).collect::<Vec<_>>()
+        };
+
+        // the user starts another branch while the answer streams
+        let pending = answer(Some(question.id));
+        let other = storage
+            .create_message_with_parent(
+                conversation.id,
+                None,
+                "Other?".to_string(),
+                DBMessageRole::User,
+            )
+            .await
+            .unwrap();
+        pending.await;
+        let leaf = storage.get_active_leaf_id(conversation.id).await.unwrap();
+        assert_eq!(leaf, Some(other.id));
+
+        // the leaf is unchanged, so the new answer is selected
+        let events = answer(Some(other.id)).await;
+        let message_id = match events.last() {
+            Some(CompletionEvent::Done { message_id, .. }) => message_id.unwrap(),
+            event => panic!("Expected done, got {:?}", event),
+        };
+        let leaf = storage.get_active_leaf_id(conversation.id).await.unwrap();
+        assert_eq!(leaf, Some(message_id));
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_conversation_completion_stores_both_turns(pool: PgPool) {
+        let (app, state) = test_app(pool).await;
+        let (alice, token) = test_user(&state, "alice@tcu.edu").await;
+        let conversation = state
+            .relational_storage
+            .create_conversation(alice.id, "Degree plan".to_string())
+            .await
+            .unwrap();
+        let uri = format!("/api/conversations/{}/completions", conversation.id);
+
+        for question in ["Which math course comes first?", "And after that?"] {
+            let body = json!({"content": question, "collection": "catalog"});
+            let response = request(&app, Method::POST, &uri, &token, Some(body)).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let events = event_names(response).await;
+            assert_eq!(events.first().map(String::as_str), Some("sources"));
+            assert_eq!(events.last().map(String::as_str), Some("done"));
+        }
+
+        let branch = state
+            .relational_storage
+            .get_active_branch(conversation.id)
+            .await
+            .unwrap();
+        let contents: Vec<&str> = branch.iter().map(|message| message.content.as_str()).collect();
+        assert_eq!(
+            contents,
+            [
+                "Which math course comes first?",
+                "You asked: Which math course comes first?",
+                "And after that?",
+                "You asked: And after that?",
+            ]
+        );
+        assert!(branch
+            .windows(2)
+            .all(|pair| pair[1].parent_id == Some(pair[0].id)));
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_failed_completion_keeps_the_question(pool: PgPool) {
+        let llm = MockAdapter::new(Model::Qwen).failure(MockFailure::Generate);
+        let (app, state) = test_app_with_llm(pool.clone(), llm).await;
+        let (alice, token) = test_user(&state, "alice@tcu.edu").await;
+        let conversation = state
+            .relational_storage
+            .create_conversation(alice.id, "Degree plan".to_string())
+            .await
+            .unwrap();
+
+        let uri = format!("/api/conversations/{}/completions", conversation.id);
+        let body = json!({"content": "Which math course comes first?", "collection": "catalog"});
+        let (status, body) = send(&app, Method::POST, &uri, &token, Some(body)).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(body["code"], "inference_failed");
+
+        assert_unanswered(&pool, conversation.id, "Which math course comes first?").await;
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_interrupted_completion_keeps_the_question(pool: PgPool) {
+        let llm = MockAdapter::new(Model::Qwen).failure(MockFailure::Stream { after_tokens: 1 });
+        let (app, state) = test_app_with_llm(pool.clone(), llm).await;
+        let (alice, token) = test_user(&state, "alice@tcu.edu").await;
+        let conversation = state
+            .relational_storage
+            .create_conversation(alice.id, "Degree plan".to_string())
+            .await
+            .unwrap();
+
+        let uri = format!("/api/conversations/{}/completions", conversation.id);
+        let body = json!({"content": "Which math course comes first?", "collection": "catalog"});
+        let response = request(&app, Method::POST, &uri, &token, Some(body)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(event_names(response).await, ["sources", "token", "error"]);
+
+        assert_unanswered(&pool, conversation.id, "Which math course comes first?").await;
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_disconnected_completion_keeps_the_question(pool: PgPool) {
+        let llm = MockAdapter::new(Model::Qwen).latency(Duration::from_millis(20));
+        let (app, state) = test_app_with_llm(pool.clone(), llm).await;
+        let (alice, token) = test_user(&state, "alice@tcu.edu").await;
+        let conversation = state
+            .relational_storage
+            .create_conversation(alice.id, "Degree plan".to_string())
+            .await
+            .unwrap();
+
+        let uri = format!("/api/conversations/{}/completions", conversation.id);
+        let body = json!({"content": "Which math course comes first?", "collection": "catalog"});
+        let response = request(&app, Method::POST, &uri, &token, Some(body)).await;
+
+        // the client goes away after the first event
+        let mut body = response.into_body().into_data_stream();
+        assert!(body.next().await.is_some());
+        drop(body);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert_unanswered(&pool, conversation.id, "Which math course comes first?").await;
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_answer_is_stored_with_generation(pool: PgPool) {
+        let (_, state) = test_app(pool.clone()).await;
+        let (alice, _) = test_user(&state, "alice@tcu.edu").await;
+
+        let conversation = state
+            .relational_storage
+            .create_conversation(alice.id, "Degree plan".to_string())
+            .await
+            .unwrap();
+        let question = state
+            .relational_storage
+            .create_message(
+                conversation.id,
+                "Which math course comes first?".to_string(),
+                DBMessageRole::User,
+            )
+            .await
+            .unwrap();
+
+        let source = Source {
+            index: 1,
+            document: "math.pdf".to_string(),
+            chunk_id: "chunk-1".to_string(),
+            score: Some(0.7),
+            snippet: "MATH 10524".to_string(),
+        };
+        let events = stream::iter(vec![
+            CompletionEvent::Sources(vec![source.clone()]),
+            CompletionEvent::Token {
+                content: "MATH ".to_string(),
+            },
+            CompletionEvent::Token {
+                content: "10524 [1]".to_string(),
+            },
+            CompletionEvent::Done {
+                model: "qwen".to_string(),
+                token_count: Some(6),
+                prompt_token_count: Some(480),
+                generation_time: Some(120),
+                message_id: None,
+            },
+        ]);
+        let pending = PendingAnswer {
+            conversation_id: conversation.id,
+            question_id: question.id,
+            active_leaf_id: Some(question.id),
+            sources: vec![source],
+            search_queries: vec!["first math course".to_string()],
+            template_version: "rag-1",
+            started: Instant::now(),
+        };
+
+        let events: Vec<CompletionEvent> = store_answer(events, state.clone(), pending)
+            .collect()
+            .await;
+        let message_id = match events.last() {
+            Some(CompletionEvent::Done { message_id, .. }) => message_id.unwrap(),
+            event => panic!("Expected done, got {:?}", event),
+        };
+
+        let answer = state
+            .relational_storage
+            .get_message_by_id(message_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(answer.content, "MATH 10524 [1]");
+        assert_eq!(answer.parent_id, Some(question.id));
+        assert_eq!(answer.model.as_deref(), Some("qwen"));
+        assert_eq!(answer.prompt_tokens, Some(480));
+        assert_eq!(answer.completion_tokens, Some(6));
+        assert_eq!(answer.template_version.as_deref(), Some("rag-1"));
+        assert!(answer.latency_ms.is_some());
+        assert_eq!(answer.sources.unwrap().0[0].chunk_id, "chunk-1");
+        let search_queries: Vec<String> =
+            sqlx::query_scalar("SELECT search_queries FROM chat.messages WHERE id = $1")
+                .bind(message_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(search_queries, ["first math course"]);
+    }
+}
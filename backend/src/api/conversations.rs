@@ -1,13 +1,22 @@
+use crate::api::error::AppError;
+use crate::api::ownership::{find_owned_conversation, OwnedConversation, OwnedMessage};
 use crate::app_state::AppState;
-use crate::storage::model::{DBMessageRole, DBUser};
-use axum::extract::{Path, State};
-use axum::http::StatusCode;
+use crate::export::{ConversationExport, ExportFormat};
+use crate::jobs::titles::{self, MAX_TITLE_LENGTH, PLACEHOLDER_TITLE};
+use crate::storage::model::{
+    DBConversation, DBConversationStatus, DBFilterOptions, DBMessageMatch, DBMessageRole,
+    DBSortOrder, DBUser,
+};
+use crate::vectorization::embedding::{cosine_similarity, EmbeddingError};
+use chrono::{DateTime, Utc};
+use axum::extract::{Query, State};
+use axum::http::{header, StatusCode};
 use axum::response::IntoResponse;
 use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::sync::Arc;
-use tracing::{error};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateMessageSchema {
@@ -17,7 +26,8 @@ pub struct CreateMessageSchema {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateConversationSchema {
-    pub title: String,
+    // without a title, one is generated after the first exchange
+    pub title: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -26,182 +36,1023 @@ pub struct UpdateConversationSchema {
     pub title: String,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EditConversationSchema {
+    pub title: Option<String>,
+    pub status: Option<DBConversationStatus>,
+}
+
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+const MAX_SEARCH_LIMIT: usize = 50;
+// semantic matches less similar to the query than this are dropped
+const SEMANTIC_SIMILARITY_THRESHOLD: f32 = 0.75;
+// most recent messages compared with the query, older ones are only found by full-text search so a
+// search costs the same for long histories
+const SEMANTIC_SCAN_LIMIT: i64 = 2000;
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SearchQuery {
+    pub q: String,
+    // also match messages with a similar meaning, not only the same words
+    pub semantic: Option<bool>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMatch {
+    Text,
+    Semantic,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SearchResult {
+    pub conversation: DBConversation,
+    pub message_id: i32,
+    pub role: DBMessageRole,
+    pub snippet: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "match")]
+    pub matched: SearchMatch,
+}
+
+impl SearchResult {
+    fn new(user: &DBUser, message: DBMessageMatch, matched: SearchMatch) -> Self {
+        Self {
+            conversation: DBConversation {
+                id: message.conversation_id,
+                owner_id: user.id,
+                title: message.title,
+                title_generated: message.title_generated,
+                last_message_at: message.last_message_at,
+                status: message.status,
+            },
+            message_id: message.message_id,
+            role: message.role,
+            snippet: message.snippet,
+            created_at: message.created_at,
+            matched,
+        }
+    }
+}
+
 /// GET /api/conversation/
 /// Authorized Endpoint -> JWT Required
-/// // TODO: generalize error message return (look at auth for this)
+/// Query: `cursor`, `limit`, `status` and `sort` (default `desc`), see DBFilterOptions
 pub async fn conversation_list_handler(
     State(state): State<Arc<AppState>>,
     Extension(user): Extension<DBUser>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let query_result = state
+    WithRejection(Query(options), _): WithRejection<Query<DBFilterOptions>, AppError>,
+) -> Result<impl IntoResponse, AppError> {
+    let page_query = options
+        .page_query(DBSortOrder::Desc)
+        .map_err(AppError::InvalidRequest)?;
+
+    let page = state
         .relational_storage
-        .get_user_conversations(user.id)
-        .await;
+        .get_user_conversations(user.id, options.status, page_query)
+        .await?;
+
+    let json_response = json!({
+        "conversations": page.items,
+        "next_cursor": page.next_cursor
+    });
+
+    Ok(Json(json_response))
+}
+
+/// GET /api/conversations/search
+/// Authorized Endpoint -> JWT Required
+/// Query: `q`, `semantic` (default false) and `limit`. Full-text matches come first, followed by
+/// semantic matches that were not found by the full-text search.
+pub async fn conversation_search_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<DBUser>,
+    WithRejection(Query(query), _): WithRejection<Query<SearchQuery>, AppError>,
+) -> Result<impl IntoResponse, AppError> {
+    let text = query.q.trim();
+    if text.is_empty() {
+        return Err(AppError::InvalidRequest(
+            "Search query must not be empty".to_string(),
+        ));
+    }
 
-    if query_result.is_err() {
-        let error_response = json!({
-            "status": "error",
-            "code": 500,
-            "message": "Failed to fetch conversations"
-        });
-        return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
+    let semantic = query.semantic.unwrap_or(false);
+    if semantic && !state.config.semantic_search {
+        return Err(AppError::InvalidRequest(
+            "Semantic search is disabled on this server".to_string(),
+        ));
     }
 
-    let conversations = query_result.unwrap();
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_SEARCH_LIMIT);
+
+    let mut results: Vec<SearchResult> = state
+        .relational_storage
+        .search_messages(user.id, text, limit)
+        .await?
+        .into_iter()
+        .map(|message| SearchResult::new(&user, message, SearchMatch::Text))
+        .collect();
+
+    if semantic && results.len() < limit {
+        // embedding is CPU bound, keep it off the async workers
+        let embedder = state.embedder;
+        let text = text.to_string();
+        let embedding = tokio::task::spawn_blocking(move || embedder(text))
+            .await
+            .map_err(|e| EmbeddingError::Message(format!("Embedding task failed: {}", e)))??;
+        let found: Vec<i32> = results.iter().map(|result| result.message_id).collect();
+
+        let matches = semantic_matches(&state, &user, &embedding, &found, limit - results.len())
+            .await?;
+        results.extend(
+            matches
+                .into_iter()
+                .map(|message| SearchResult::new(&user, message, SearchMatch::Semantic)),
+        );
+    }
 
     let json_response = json!({
-        "conversations": conversations
+        "results": results
     });
 
     Ok(Json(json_response))
 }
 
+// The user's messages most similar to `embedding`, most similar first, without `exclude`.
+// Only the latest messages already embedded by jobs::embeddings are considered.
+async fn semantic_matches(
+    state: &AppState,
+    user: &DBUser,
+    embedding: &[f32],
+    exclude: &[i32],
+    limit: usize,
+) -> Result<Vec<DBMessageMatch>, AppError> {
+    let mut scored: Vec<(i32, f32)> = state
+        .relational_storage
+        .get_message_embeddings(user.id, SEMANTIC_SCAN_LIMIT)
+        .await?
+        .into_iter()
+        .filter(|message| !exclude.contains(&message.message_id))
+        .map(|message| {
+            (
+                message.message_id,
+                cosine_similarity(embedding, &message.embedding),
+            )
+        })
+        .filter(|(_, similarity)| *similarity >= SEMANTIC_SIMILARITY_THRESHOLD)
+        .collect();
+
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(limit);
+
+    let ids: Vec<i32> = scored.into_iter().map(|(id, _)| id).collect();
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    Ok(state
+        .relational_storage
+        .get_message_matches(user.id, &ids)
+        .await?)
+}
+
 /// POST /api/conversation
 pub async fn conversation_new_handler(
     State(state): State<Arc<AppState>>,
     Extension(user): Extension<DBUser>,
     Json(payload): Json<CreateConversationSchema>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let query_result = state
+) -> Result<impl IntoResponse, AppError> {
+    let title = match payload.title {
+        Some(title) => validate_title(&title)?.to_string(),
+        None => PLACEHOLDER_TITLE.to_string(),
+    };
+
+    let conversation = state
         .relational_storage
-        .create_conversation(user.id, payload.title)
-        .await;
+        .create_conversation(user.id, title)
+        .await?;
 
-    match query_result {
-        Ok(conversation) => {
-            let conversation_response = json!({
-                "conversation_id": conversation.id
-            });
+    let conversation_response = json!({
+        "conversation_id": conversation.id
+    });
 
-            Ok((StatusCode::CREATED, Json(conversation_response)))
-        }
-        Err(e) => {
-            // TODO: make sure this doesn't leak sensitive info
-            let error_response = json!({
-            "status": "error",
-            "code": 500,
-            "message": e.to_string(),
-            });
-            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)))
-        }
-    }
+    Ok((StatusCode::CREATED, Json(conversation_response)))
 }
 
 /// GET /api/conversation/{conversation_id}/messages
 /// Authorized Endpoint -> JWT Required
+/// Query: `cursor`, `limit` and `sort` (default `asc`), see DBFilterOptions
 pub async fn conversation_list_messages(
-    Path(conversation_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+    OwnedConversation(conversation): OwnedConversation,
+    WithRejection(Query(options), _): WithRejection<Query<DBFilterOptions>, AppError>,
+) -> Result<impl IntoResponse, AppError> {
+    let page_query = options
+        .page_query(DBSortOrder::Asc)
+        .map_err(AppError::InvalidRequest)?;
+
+    let page = state
+        .relational_storage
+        .get_conversation_messages_page(conversation.id, page_query)
+        .await?;
+
+    let json_response = json!({
+        "messages": page.items,
+        "next_cursor": page.next_cursor
+    });
+
+    Ok(Json(json_response))
+}
+
+/// GET /api/conversations/{conversation_id}/export
+/// Authorized Endpoint -> JWT Required
+/// Query: `format`, one of `md` (default), `json` or `pdf`. Exports the active branch.
+pub async fn conversation_export_handler(
+    State(state): State<Arc<AppState>>,
+    OwnedConversation(conversation): OwnedConversation,
+    WithRejection(Query(query), _): WithRejection<Query<ExportQuery>, AppError>,
+) -> Result<impl IntoResponse, AppError> {
+    let messages = state
+        .relational_storage
+        .get_active_branch(conversation.id)
+        .await?;
+
+    let disposition = format!(
+        "attachment; filename=\"echelon-conversation-{}.{}\"",
+        conversation.id,
+        query.format.extension()
+    );
+    let body = ConversationExport::new(conversation, messages).render(query.format)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, query.format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    ))
+}
+
+/// POST /api/conversation/{conversation_id}/messages
+/// Authorized Endpoint -> JWT Required
+pub async fn conversation_new_message_handler(
+    State(state): State<Arc<AppState>>,
+    OwnedConversation(conversation): OwnedConversation,
+    Json(payload): Json<CreateMessageSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    let message = state
+        .relational_storage
+        .create_message(conversation.id, payload.content, payload.role)
+        .await?;
+
+    // clients that store answers themselves get a title after the first one as well
+    if matches!(message.role, DBMessageRole::Assistant) {
+        titles::spawn(state.clone(), conversation.id);
+    }
+
+    let message_response = json!({
+        "conversation_id": message.id
+    });
+
+    Ok((StatusCode::CREATED, Json(message_response)))
+}
+
+/// PUT /api/conversation
+/// Authorized Endpoint -> JWT Required
+pub async fn conversation_update_handler(
     State(state): State<Arc<AppState>>,
     Extension(user): Extension<DBUser>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    // fetch convo and check user id, use SQL qeury and compare
-    let fetch_conversations = match state
+    Json(payload): Json<UpdateConversationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    // the id comes from the payload instead of the path, so the extractor cannot be used here
+    let conversation = find_owned_conversation(&state, &user, payload.id).await?;
+    let title = validate_title(&payload.title)?;
+    ensure_user_editable(&conversation)?;
+
+    state
         .relational_storage
-        .get_conversation_by_id(conversation_id)
-        .await {
-        Ok(response) => { response }
-        Err(e) => {
-            error!("{}", e);
-            let error_response = json!({
-            "message": format!("Failed to fetch conversation {}", {conversation_id})
-        });
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
-        }
-    };
+        .update_conversation(&conversation.id, Some(title), None)
+        .await?;
 
-    if fetch_conversations.is_empty() {
-        let error_response = json!({
-        "message": format!("Conversation not found: {}", conversation_id)
+    let json_response = json!({
+        "message": "Successfully updated conversation"
     });
-        return Err((StatusCode::NOT_FOUND, Json(error_response)));
+
+    Ok((StatusCode::CREATED, Json(json_response)))
+}
+
+/// PUT /api/conversations/{conversation_id}
+/// Authorized Endpoint -> JWT Required
+/// Changes the title and/or moves the conversation between active, archived and starred
+pub async fn conversation_edit_handler(
+    State(state): State<Arc<AppState>>,
+    OwnedConversation(conversation): OwnedConversation,
+    Json(payload): Json<EditConversationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    if payload.title.is_none() && payload.status.is_none() {
+        return Err(AppError::InvalidRequest(
+            "Provide a title or a status to update".to_string(),
+        ));
     }
 
-    for conversation in &fetch_conversations {
-        if conversation.owner_id != user.id {
-            let error_response = json!({
-            "message": format!("Unauthorized access to conversation {}", conversation_id)
-        });
-            return Err((StatusCode::UNAUTHORIZED, Json(error_response)));
-        }
+    let title = payload.title.as_deref().map(validate_title).transpose()?;
+    ensure_user_editable(&conversation)?;
+
+    if payload
+        .status
+        .is_some_and(|status| !status.is_user_settable())
+    {
+        return Err(AppError::InvalidRequest(
+            "Status 'system' cannot be set".to_string(),
+        ));
     }
 
-    let query_result = state
+    let conversation = state
         .relational_storage
-        .get_conversation_messages(conversation_id)
-        .await;
+        .update_conversation(&conversation.id, title, payload.status)
+        .await?;
 
-    if query_result.is_err() {
-        let error_response = json!({
-            "message": format!("Failed to fetch messages for conversation {}", {conversation_id})
-        });
-        return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
+    let json_response = json!({
+        "conversation": conversation
+    });
+
+    Ok(Json(json_response))
+}
+
+/// System conversations are managed by the server, their owner can neither rename nor move them.
+fn ensure_user_editable(conversation: &DBConversation) -> Result<(), AppError> {
+    if !conversation.status.is_user_settable() {
+        return Err(AppError::InvalidRequest(
+            "System conversations cannot be changed".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Trims a title set by the owner and checks its length.
+fn validate_title(title: &str) -> Result<&str, AppError> {
+    let title = title.trim();
+
+    if title.is_empty() {
+        return Err(AppError::InvalidRequest(
+            "Title must not be empty".to_string(),
+        ));
+    }
+
+    if title.chars().count() > MAX_TITLE_LENGTH {
+        return Err(AppError::InvalidRequest(format!(
+            "Title must be at most {} characters",
+            MAX_TITLE_LENGTH
+        )));
     }
 
-    let messages = query_result.unwrap();
+    Ok(title)
+}
+
+/// DELETE /api/conversations/{conversation_id}
+/// Authorized Endpoint -> JWT Required
+/// Soft delete, the conversation is removed for good by the retention job
+pub async fn conversation_delete_handler(
+    State(state): State<Arc<AppState>>,
+    OwnedConversation(conversation): OwnedConversation,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .relational_storage
+        .soft_delete_conversation(&conversation.id)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/conversations/{conversation_id}/messages/{message_id}/siblings
+/// Authorized Endpoint -> JWT Required
+/// Alternative versions of a message created by regenerating or editing, oldest first
+pub async fn conversation_message_siblings_handler(
+    State(state): State<Arc<AppState>>,
+    OwnedMessage(conversation, message): OwnedMessage,
+) -> Result<impl IntoResponse, AppError> {
+    let siblings = state
+        .relational_storage
+        .get_message_siblings(&message)
+        .await?;
+
+    let active_branch = state
+        .relational_storage
+        .get_active_branch(conversation.id)
+        .await?;
+
+    // the sibling on the active branch, if the branch passes through this level
+    let active_id = siblings
+        .iter()
+        .map(|sibling| sibling.id)
+        .find(|id| active_branch.iter().any(|message| message.id == *id));
 
     let json_response = json!({
-        "messages": messages
+        "siblings": siblings,
+        "active_id": active_id
     });
 
     Ok(Json(json_response))
 }
 
-/// POST /api/conversation/{conversation_id}/messages
-pub async fn conversation_new_message_handler(
-    Path(conversation_id): Path<i32>,
+/// POST /api/conversations/{conversation_id}/messages/{message_id}/select
+/// Authorized Endpoint -> JWT Required
+/// Makes the branch through the message active, following the newest reply below it
+pub async fn conversation_select_branch_handler(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<CreateMessageSchema>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let query_result = state
+    OwnedMessage(conversation, message): OwnedMessage,
+) -> Result<impl IntoResponse, AppError> {
+    let active_leaf_id = state
         .relational_storage
-        .create_message(conversation_id, payload.content, payload.role)
-        .await;
+        .select_branch(conversation.id, message.id)
+        .await?;
 
-    match query_result {
-        Ok(message) => {
-            let message_response = json!({
-                "conversation_id": message.id
-            });
+    let json_response = json!({
+        "active_leaf_id": active_leaf_id
+    });
 
-            Ok((StatusCode::CREATED, Json(message_response)))
+    Ok(Json(json_response))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::conversations::semantic_matches;
+    use crate::api::testing::{send, test_app, test_user};
+    use crate::jobs::embeddings::embed_pending;
+    use crate::storage::model::DBMessageRole;
+    use crate::vectorization::embedding::EmbeddingError;
+    use fastembed::Embedding;
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+    use sqlx::PgPool;
+
+    #[sqlx::test(migrations = false)]
+    async fn test_conversation_pagination(pool: PgPool) {
+        let (app, state) = test_app(pool.clone()).await;
+        let (alice, token) = test_user(&state, "alice@tcu.edu").await;
+        let (bob, _) = test_user(&state, "bob@tcu.edu").await;
+
+        let mut created = vec![];
+        for idx in 0..5 {
+            let conversation = state
+                .relational_storage
+                .create_conversation(alice.id, format!("Conversation {}", idx))
+                .await
+                .unwrap();
+            created.push(conversation.id);
+        }
+        state
+            .relational_storage
+            .create_conversation(bob.id, "Not yours".to_string())
+            .await
+            .unwrap();
+
+        sqlx::query("UPDATE chat.conversations SET status = 'starred' WHERE id = $1")
+            .bind(created[1])
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        // walk every page, newest first
+        let mut listed = vec![];
+        let mut uri = "/api/conversations?limit=2".to_string();
+        loop {
+            let (status, body) = send(&app, Method::GET, &uri, &token, None).await;
+            assert_eq!(status, StatusCode::OK);
+
+            for conversation in body["conversations"].as_array().unwrap() {
+                listed.push(conversation["id"].as_i64().unwrap() as i32);
+            }
+
+            match body["next_cursor"].as_str() {
+                Some(cursor) => uri = format!("/api/conversations?limit=2&cursor={}", cursor),
+                None => break,
+            }
         }
-        Err(e) => {
-            // TODO: make sure this doesn't leak sensitive info
-            let error_response = json!({
-            "status": "error",
-            "code": 500,
-            "message": e.to_string(),
-            });
-            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)))
+        created.reverse();
+        assert_eq!(listed, created);
+
+        let (_, body) = send(
+            &app,
+            Method::GET,
+            "/api/conversations?status=starred",
+            &token,
+            None,
+        )
+        .await;
+        let starred = body["conversations"].as_array().unwrap();
+        assert_eq!(starred.len(), 1);
+        assert_eq!(starred[0]["status"], "Starred");
+
+        let (status, body) = send(
+            &app,
+            Method::GET,
+            "/api/conversations?cursor=oops",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_request");
+
+        let (status, _) = send(
+            &app,
+            Method::GET,
+            "/api/conversations?status=deleted",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_message_pagination(pool: PgPool) {
+        let (app, state) = test_app(pool).await;
+        let (alice, token) = test_user(&state, "alice@tcu.edu").await;
+
+        let conversation = state
+            .relational_storage
+            .create_conversation(alice.id, "Degree plan".to_string())
+            .await
+            .unwrap();
+        for idx in 0..3 {
+            state
+                .relational_storage
+                .create_message(
+                    conversation.id,
+                    format!("Message {}", idx),
+                    DBMessageRole::User,
+                )
+                .await
+                .unwrap();
         }
+
+        let uri = format!("/api/conversations/{}/messages?limit=2", conversation.id);
+        let (_, body) = send(&app, Method::GET, &uri, &token, None).await;
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["content"], "Message 0");
+
+        let uri = format!("{}&cursor={}", uri, body["next_cursor"].as_str().unwrap());
+        let (_, body) = send(&app, Method::GET, &uri, &token, None).await;
+        assert_eq!(body["messages"][0]["content"], "Message 2");
+        assert!(body["next_cursor"].is_null());
+
+        let uri = format!(
+            "/api/conversations/{}/messages?limit=1&sort=desc",
+            conversation.id
+        );
+        let (_, body) = send(&app, Method::GET, &uri, &token, None).await;
+        assert_eq!(body["messages"][0]["content"], "Message 2");
     }
-}
 
-pub async fn conversation_update_handler(
-    State(state): State<Arc<AppState>>,
-    Json(payload): Json<UpdateConversationSchema>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    match state
-        .relational_storage
-        .update_conversation(
-            &payload.id,
-            &payload.title,
+    #[sqlx::test(migrations = false)]
+    async fn test_status_transitions_and_delete(pool: PgPool) {
+        let (app, state) = test_app(pool.clone()).await;
+        let (alice, token) = test_user(&state, "alice@tcu.edu").await;
+
+        let conversation = state
+            .relational_storage
+            .create_conversation(alice.id, "Degree plan".to_string())
+            .await
+            .unwrap();
+        let uri = format!("/api/conversations/{}", conversation.id);
+
+        let (status, body) = send(
+            &app,
+            Method::PUT,
+            &uri,
+            &token,
+            Some(json!({"status": "starred"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["conversation"]["status"], "Starred");
+        assert_eq!(body["conversation"]["title"], "Degree plan");
+
+        let edit = json!({"title": "Spring plan", "status": "archived"});
+        let (_, body) = send(&app, Method::PUT, &uri, &token, Some(edit)).await;
+        assert_eq!(body["conversation"]["status"], "Archived");
+        assert_eq!(body["conversation"]["title"], "Spring plan");
+
+        let (status, _) = send(
+            &app,
+            Method::PUT,
+            &uri,
+            &token,
+            Some(json!({"status": "system"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = send(&app, Method::PUT, &uri, &token, Some(json!({}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let edit = json!({"title": "a".repeat(256)});
+        let (status, _) = send(&app, Method::PUT, &uri, &token, Some(edit)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = send(&app, Method::DELETE, &uri, &token, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        // soft-deleted conversations disappear from the API but stay in the database
+        let (status, _) = send(&app, Method::DELETE, &uri, &token, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (_, body) = send(&app, Method::GET, "/api/conversations", &token, None).await;
+        assert!(body["conversations"].as_array().unwrap().is_empty());
+
+        let stored: i64 = sqlx::query_scalar(
+            "SELECT count(*) FROM chat.conversations WHERE deleted_at IS NOT NULL",
         )
+        .fetch_one(&pool)
         .await
-    {
-        Err(e) => {
-            // SQL error, failed to execute SQL
-            error!("Failed on SQL fetch: {}", e.to_string());
-            let error_response = json!({
-                "message": "Unable to update conversation due to a server error",
-            });
-            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)))
+        .unwrap();
+        assert_eq!(stored, 1);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_system_conversations_cannot_be_changed(pool: PgPool) {
+        let (app, state) = test_app(pool.clone()).await;
+        let (alice, token) = test_user(&state, "alice@tcu.edu").await;
+
+        let conversation = state
+            .relational_storage
+            .create_conversation(alice.id, "Welcome".to_string())
+            .await
+            .unwrap();
+        sqlx::query("UPDATE chat.conversations SET status = 'system' WHERE id = $1")
+            .bind(conversation.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let uri = format!("/api/conversations/{}", conversation.id);
+        let edit = json!({"title": "Renamed"});
+        let (status, _) = send(&app, Method::PUT, &uri, &token, Some(edit)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // the legacy route with the id in the body applies the same rule
+        let edit = json!({"id": conversation.id, "title": "Renamed"});
+        let (status, _) = send(&app, Method::PUT, "/api/conversations", &token, Some(edit)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let title: String = sqlx::query_scalar("SELECT title FROM chat.conversations WHERE id = $1")
+            .bind(conversation.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(title, "Welcome");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_message_branches(pool: PgPool) {
+        let (app, state) = test_app(pool).await;
+        let (alice, token) = test_user(&state, "alice@tcu.edu").await;
+        let storage = &state.relational_storage;
+
+        let conversation = storage
+            .create_conversation(alice.id, "Degree plan".to_string())
+            .await
+            .unwrap();
+        let other = storage
+            .create_conversation(alice.id, "Other".to_string())
+            .await
+            .unwrap();
+
+        let mut ids = vec![];
+        for (content, role) in [
+            ("Q1", DBMessageRole::User),
+            ("A1", DBMessageRole::Assistant),
+            ("Q2", DBMessageRole::User),
+            ("A2", DBMessageRole::Assistant),
+        ] {
+            let message = storage
+                .create_message(conversation.id, content.to_string(), role)
+                .await
+                .unwrap();
+            ids.push(message.id);
+        }
+
+        // regenerated answer to Q2
+        let regenerated = storage
+            .create_message_with_parent(
+                conversation.id,
+                Some(ids[2]),
+                "A2 again".to_string(),
+                DBMessageRole::Assistant,
+            )
+            .await
+            .unwrap();
+
+        let contents = |body: serde_json::Value| -> Vec<String> {
+            body["messages"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|message| message["content"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let messages_uri = format!("/api/conversations/{}/messages", conversation.id);
+        let (_, body) = send(&app, Method::GET, &messages_uri, &token, None).await;
+        assert_eq!(contents(body), vec!["Q1", "A1", "Q2", "A2 again"]);
+
+        let siblings_uri = format!(
+            "/api/conversations/{}/messages/{}/siblings",
+            conversation.id, ids[3]
+        );
+        let (status, body) = send(&app, Method::GET, &siblings_uri, &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["siblings"].as_array().unwrap().len(), 2);
+        assert_eq!(body["active_id"], regenerated.id);
+
+        let select_uri = format!(
+            "/api/conversations/{}/messages/{}/select",
+            conversation.id, ids[3]
+        );
+        let (_, body) = send(&app, Method::POST, &select_uri, &token, None).await;
+        assert_eq!(body["active_leaf_id"], ids[3]);
+
+        let (_, body) = send(&app, Method::GET, &messages_uri, &token, None).await;
+        assert_eq!(contents(body), vec!["Q1", "A1", "Q2", "A2"]);
+
+        // edited question, selecting the original again follows its newest answer
+        storage
+            .create_message_with_parent(
+                conversation.id,
+                Some(ids[1]),
+                "Q2 edited".to_string(),
+                DBMessageRole::User,
+            )
+            .await
+            .unwrap();
+        let (_, body) = send(&app, Method::GET, &messages_uri, &token, None).await;
+        assert_eq!(contents(body), vec!["Q1", "A1", "Q2 edited"]);
+
+        let select_uri = format!(
+            "/api/conversations/{}/messages/{}/select",
+            conversation.id, ids[2]
+        );
+        let (_, body) = send(&app, Method::POST, &select_uri, &token, None).await;
+        assert_eq!(body["active_leaf_id"], regenerated.id);
+
+        // invalid targets are rejected before anything is generated
+        let collection = json!({"collection": "catalog"});
+        let regenerate_uri = format!(
+            "/api/conversations/{}/messages/{}/regenerate",
+            conversation.id, ids[2]
+        );
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            &regenerate_uri,
+            &token,
+            Some(collection),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let edit = json!({"content": "Q1 edited", "collection": "catalog"});
+        let edit_uri = format!(
+            "/api/conversations/{}/messages/{}/edit",
+            conversation.id, ids[1]
+        );
+        let (status, _) = send(&app, Method::POST, &edit_uri, &token, Some(edit.clone())).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let edit_uri = format!("/api/conversations/{}/messages/{}/edit", other.id, ids[0]);
+        let (status, _) = send(&app, Method::POST, &edit_uri, &token, Some(edit)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_conversation_search(pool: PgPool) {
+        let (app, state) = test_app(pool).await;
+        let (alice, alice_token) = test_user(&state, "alice@tcu.edu").await;
+        let (bob, bob_token) = test_user(&state, "bob@tcu.edu").await;
+        let storage = &state.relational_storage;
+
+        let minor = storage
+            .create_conversation(alice.id, "Minors".to_string())
+            .await
+            .unwrap();
+        let question = storage
+            .create_message(
+                minor.id,
+                "What does the computer science minor require?".to_string(),
+                DBMessageRole::User,
+            )
+            .await
+            .unwrap();
+        let deleted = storage
+            .create_conversation(alice.id, "Deleted".to_string())
+            .await
+            .unwrap();
+        storage
+            .create_message(
+                deleted.id,
+                "Is the minor worth it?".to_string(),
+                DBMessageRole::User,
+            )
+            .await
+            .unwrap();
+        storage.soft_delete_conversation(&deleted.id).await.unwrap();
+        let other = storage
+            .create_conversation(bob.id, "Bob's minor".to_string())
+            .await
+            .unwrap();
+        storage
+            .create_message(
+                other.id,
+                "Which minors pair with science majors?".to_string(),
+                DBMessageRole::User,
+            )
+            .await
+            .unwrap();
+
+        let (status, body) = send(
+            &app,
+            Method::GET,
+            "/api/conversations/search?q=science%20minor",
+            &alice_token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let results = body["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["message_id"], question.id);
+        assert_eq!(results[0]["conversation"]["title"], "Minors");
+        assert_eq!(results[0]["match"], "text");
+        assert!(results[0]["snippet"]
+            .as_str()
+            .unwrap()
+            .contains("<mark>minor</mark>"));
+
+        let (_, body) = send(
+            &app,
+            Method::GET,
+            "/api/conversations/search?q=minor",
+            &bob_token,
+            None,
+        )
+        .await;
+        let results = body["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["conversation"]["id"], other.id);
+
+        for uri in [
+            "/api/conversations/search?q=%20",
+            "/api/conversations/search",
+            "/api/conversations/search?q=minor&semantic=true",
+        ] {
+            let (status, _) = send(&app, Method::GET, uri, &alice_token, None).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
         }
-        Ok(_) => {
-            let json_response = json!({
-                "message": "Successfully updated conversation"
-            });
-            Ok((StatusCode::CREATED, Json(json_response)))
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_search_snippet_is_escaped(pool: PgPool) {
+        let (app, state) = test_app(pool).await;
+        let (alice, alice_token) = test_user(&state, "alice@tcu.edu").await;
+        let storage = &state.relational_storage;
+
+        let conversation = storage
+            .create_conversation(alice.id, "Pasted".to_string())
+            .await
+            .unwrap();
+        storage
+            .create_message(
+                conversation.id,
+                "<script>alert(1)</script> Does the minor need MATH & COSC?".to_string(),
+                DBMessageRole::User,
+            )
+            .await
+            .unwrap();
+
+        let (status, body) = send(
+            &app,
+            Method::GET,
+            "/api/conversations/search?q=minor",
+            &alice_token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let snippet = body["results"][0]["snippet"].as_str().unwrap();
+        assert!(!snippet.contains("<script>"), "{}", snippet);
+        assert!(snippet.contains("&lt;/script&gt;"), "{}", snippet);
+        assert!(snippet.contains("&amp;"), "{}", snippet);
+        assert!(snippet.contains("<mark>minor</mark>"), "{}", snippet);
+    }
+
+    // messages about minors point one way, everything else the other
+    fn topic_embedder(texts: &[String]) -> Result<Vec<Embedding>, EmbeddingError> {
+        Ok(texts
+            .iter()
+            .map(|text| match text.contains("minor") {
+                true => vec![1.0, 0.1],
+                false => vec![0.0, 1.0],
+            })
+            .collect())
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_semantic_matches(pool: PgPool) {
+        let (_, state) = test_app(pool).await;
+        let (alice, _) = test_user(&state, "alice@tcu.edu").await;
+        let (bob, _) = test_user(&state, "bob@tcu.edu").await;
+        let storage = &state.relational_storage;
+
+        let mut ids = vec![];
+        for (user, content) in [
+            (&alice, "Can I add a minor in math?"),
+            (&alice, "When does registration open?"),
+            (&alice, "Which minor fits a CS major?"),
+            (&bob, "Is a minor required?"),
+        ] {
+            let conversation = storage
+                .create_conversation(user.id, "Questions".to_string())
+                .await
+                .unwrap();
+            let message = storage
+                .create_message(conversation.id, content.to_string(), DBMessageRole::User)
+                .await
+                .unwrap();
+            ids.push(message.id);
+        }
+
+        assert_eq!(embed_pending(storage, topic_embedder).await.unwrap(), 4);
+        assert_eq!(embed_pending(storage, topic_embedder).await.unwrap(), 0);
+
+        let matches = semantic_matches(&state, &alice, &[1.0, 0.0], &[ids[0]], 10)
+            .await
+            .unwrap();
+        let found: Vec<i32> = matches.iter().map(|message| message.message_id).collect();
+        assert_eq!(found, vec![ids[2]]);
+
+        // the scan starts at the newest message
+        let latest = storage.get_message_embeddings(alice.id, 1).await.unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].message_id, ids[2]);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_conversation_export(pool: PgPool) {
+        let (app, state) = test_app(pool).await;
+        let (alice, token) = test_user(&state, "alice@tcu.edu").await;
+        let storage = &state.relational_storage;
+
+        let conversation = storage
+            .create_conversation(alice.id, "Degree plan".to_string())
+            .await
+            .unwrap();
+        let first = storage
+            .create_message(conversation.id, "First".to_string(), DBMessageRole::User)
+            .await
+            .unwrap();
+        storage
+            .create_message(conversation.id, "Answer".to_string(), DBMessageRole::Assistant)
+            .await
+            .unwrap();
+        // an edit moves the active branch away from the first question
+        storage
+            .create_message_with_parent(
+                conversation.id,
+                first.parent_id,
+                "Edited".to_string(),
+                DBMessageRole::User,
+            )
+            .await
+            .unwrap();
+
+        let uri = format!("/api/conversations/{}/export", conversation.id);
+        let (status, body) = send(&app, Method::GET, &format!("{}?format=json", uri), &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["conversation"]["title"], "Degree plan");
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["content"], "Edited");
+
+        for query in ["", "?format=md", "?format=pdf"] {
+            let (status, _) = send(&app, Method::GET, &format!("{}{}", uri, query), &token, None).await;
+            assert_eq!(status, StatusCode::OK, "{}", query);
         }
+
+        let (status, _) = send(&app, Method::GET, &format!("{}?format=docx", uri), &token, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,241 @@
+use crate::export::ExportError;
+use crate::llm::inference::InferenceError;
+use crate::rag::pipeline::RagError;
+use crate::storage::vector::VectorStorageError;
+use crate::vectorization::embedding::EmbeddingError;
+use axum::extract::rejection::{PathRejection, QueryRejection};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use jsonwebtoken::errors::ErrorKind;
+use serde::Serialize;
+use serde_json::json;
+use thiserror::Error;
+use tracing::{error, info};
+
+/// Error returned by every handler, rendered as `{"code": "...", "message": "..."}`.
+///
+/// Client errors carry a message meant for the user. Server errors are logged with their
+/// cause and respond with a generic message, so database or backend details never leak.
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("No JWT found, please provide one")]
+    MissingToken,
+    #[error("Incorrect email or password")]
+    InvalidCredentials,
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error(transparent)]
+    VectorStorage(#[from] VectorStorageError),
+    #[error(transparent)]
+    Embedding(#[from] EmbeddingError),
+    #[error(transparent)]
+    Inference(#[from] InferenceError),
+    #[error("JWT error: {0}")]
+    Jwt(#[from] jsonwebtoken::errors::Error),
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+/// Machine-readable error codes, these are part of the API and must not be renamed.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidRequest,
+    MissingToken,
+    InvalidToken,
+    InvalidCredentials,
+    Forbidden,
+    NotFound,
+    Conflict,
+    DatabaseError,
+    RetrievalFailed,
+    InferenceFailed,
+    InternalError,
+}
+
+impl AppError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            AppError::InvalidRequest(_) => ErrorCode::InvalidRequest,
+            AppError::MissingToken => ErrorCode::MissingToken,
+            AppError::InvalidCredentials => ErrorCode::InvalidCredentials,
+            AppError::Forbidden(_) => ErrorCode::Forbidden,
+            AppError::NotFound(_) => ErrorCode::NotFound,
+            AppError::Conflict(_) => ErrorCode::Conflict,
+            AppError::Database(_) => ErrorCode::DatabaseError,
+            AppError::VectorStorage(_) | AppError::Embedding(_) => ErrorCode::RetrievalFailed,
+            AppError::Inference(_) => ErrorCode::InferenceFailed,
+            AppError::Jwt(e) if is_token_error(e) => ErrorCode::InvalidToken,
+            AppError::Jwt(_) | AppError::Internal(_) => ErrorCode::InternalError,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self.code() {
+            ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::MissingToken | ErrorCode::InvalidToken | ErrorCode::InvalidCredentials => {
+                StatusCode::UNAUTHORIZED
+            }
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::RetrievalFailed | ErrorCode::InferenceFailed => StatusCode::BAD_GATEWAY,
+            ErrorCode::DatabaseError | ErrorCode::InternalError => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    // the message sent to the client
+    fn public_message(&self) -> String {
+        match self {
+            AppError::InvalidRequest(message)
+            | AppError::Forbidden(message)
+            | AppError::NotFound(message)
+            | AppError::Conflict(message) => message.clone(),
+            AppError::MissingToken | AppError::InvalidCredentials => self.to_string(),
+            AppError::Jwt(e) if is_token_error(e) => "Invalid or expired JWT".to_string(),
+            AppError::VectorStorage(_) | AppError::Embedding(_) => {
+                "Unable to search the knowledge base".to_string()
+            }
+            AppError::Inference(_) => "The model failed while generating a response".to_string(),
+            AppError::Database(_) | AppError::Jwt(_) | AppError::Internal(_) => {
+                "Unable to complete the request due to a server error".to_string()
+            }
+        }
+    }
+}
+
+// errors caused by the token the client sent, as opposed to the server's keys
+fn is_token_error(e: &jsonwebtoken::errors::Error) -> bool {
+    !matches!(
+        e.kind(),
+        ErrorKind::InvalidEcdsaKey
+            | ErrorKind::InvalidRsaKey(_)
+            | ErrorKind::RsaFailedSigning
+            | ErrorKind::InvalidKeyFormat
+            | ErrorKind::Crypto(_)
+    )
+}
+
+impl From<RagError> for AppError {
+    fn from(e: RagError) -> Self {
+        match e {
+            RagError::MissingQuestion => {
+                AppError::InvalidRequest("No user message to create a completion for".to_string())
+            }
+            RagError::Embedding(e) => AppError::Embedding(e),
+            RagError::VectorStorage(e) => AppError::VectorStorage(e),
+            RagError::Inference(e) => AppError::Inference(e),
+        }
+    }
+}
+
+impl From<ExportError> for AppError {
+    fn from(e: ExportError) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(e: QueryRejection) -> Self {
+        AppError::InvalidRequest(e.body_text())
+    }
+}
+
+impl From<PathRejection> for AppError {
+    fn from(e: PathRejection) -> Self {
+        AppError::InvalidRequest(e.body_text())
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+
+        if status.is_server_error() {
+            error!("{}", self);
+        } else {
+            info!("{}", self);
+        }
+
+        let body = json!({
+            "code": self.code(),
+            "message": self.public_message(),
+        });
+
+        (status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::error::{AppError, ErrorCode};
+    use crate::llm::inference::InferenceError;
+    use crate::rag::pipeline::RagError;
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+    use jsonwebtoken::errors::ErrorKind;
+
+    async fn render(error: AppError) -> (StatusCode, serde_json::Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_hide_internals() {
+        let error = AppError::from(sqlx::Error::Protocol(
+            "relation \"chat.users\" does not exist".to_string(),
+        ));
+        let (status, body) = render(error).await;
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["code"], "database_error");
+        assert!(!body["message"].as_str().unwrap().contains("chat.users"));
+
+        let error = AppError::from(RagError::Inference(InferenceError::Message(
+            "connection refused to 10.0.0.4:11434".to_string(),
+        )));
+        let (status, body) = render(error).await;
+
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(body["code"], "inference_failed");
+        assert!(!body["message"].as_str().unwrap().contains("10.0.0.4"));
+    }
+
+    #[tokio::test]
+    async fn test_client_errors() {
+        let (status, body) = render(AppError::from(RagError::MissingQuestion)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_request");
+        assert_eq!(
+            body["message"],
+            "No user message to create a completion for"
+        );
+
+        let expired = AppError::from(jsonwebtoken::errors::Error::from(
+            ErrorKind::ExpiredSignature,
+        ));
+        assert_eq!(expired.code(), ErrorCode::InvalidToken);
+        assert_eq!(expired.status(), StatusCode::UNAUTHORIZED);
+
+        let bad_key = AppError::from(jsonwebtoken::errors::Error::from(
+            ErrorKind::InvalidKeyFormat,
+        ));
+        assert_eq!(bad_key.code(), ErrorCode::InternalError);
+    }
+}
@@ -0,0 +1,237 @@
+use crate::api::error::AppError;
+use crate::api::ownership::find_owned_message;
+use crate::app_state::AppState;
+use crate::storage::model::{DBFeedbackCategory, DBFeedbackRating, DBMessageRole, DBUser};
+use axum::extract::{Path, Query, State};
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+
+const MAX_COMMENT_LENGTH: usize = 2000;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MessageFeedbackSchema {
+    pub rating: DBFeedbackRating,
+    // only allowed with a `down` rating
+    pub category: Option<DBFeedbackCategory>,
+    pub comment: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct FeedbackExportQuery {
+    pub rating: Option<DBFeedbackRating>,
+    pub category: Option<DBFeedbackCategory>,
+    // only feedback given or changed at or after this time
+    pub since: Option<DateTime<Utc>>,
+}
+
+/// POST /api/messages/{message_id}/feedback -> JWT required
+/// Rates an answer in one of the user's conversations, rating it again replaces the feedback.
+pub async fn message_feedback_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<DBUser>,
+    WithRejection(Path(message_id), _): WithRejection<Path<i32>, AppError>,
+    Json(payload): Json<MessageFeedbackSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    let (_, message) = find_owned_message(&state, &user, message_id).await?;
+
+    if !matches!(message.role, DBMessageRole::Assistant) {
+        return Err(AppError::InvalidRequest(
+            "Only answers can be rated".to_string(),
+        ));
+    }
+
+    if payload.category.is_some() && payload.rating != DBFeedbackRating::Down {
+        return Err(AppError::InvalidRequest(
+            "A category can only be given with a 'down' rating".to_string(),
+        ));
+    }
+
+    let comment = payload
+        .comment
+        .as_deref()
+        .map(str::trim)
+        .filter(|comment| !comment.is_empty());
+
+    if comment.is_some_and(|comment| comment.chars().count() > MAX_COMMENT_LENGTH) {
+        return Err(AppError::InvalidRequest(format!(
+            "Comment must be at most {} characters",
+            MAX_COMMENT_LENGTH
+        )));
+    }
+
+    let feedback = state
+        .relational_storage
+        .upsert_message_feedback(
+            message.id,
+            user.id,
+            payload.rating,
+            payload.category,
+            comment,
+        )
+        .await?;
+
+    let json_response = json!({
+        "feedback": feedback
+    });
+
+    Ok(Json(json_response))
+}
+
+/// GET /api/admin/feedback -> JWT of an admin required
+/// Exports feedback with the question, answer, model and sources it refers to, newest first.
+pub async fn admin_feedback_export_handler(
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(query), _): WithRejection<Query<FeedbackExportQuery>, AppError>,
+) -> Result<impl IntoResponse, AppError> {
+    let feedback = state
+        .relational_storage
+        .get_feedback_export(query.rating, query.category, query.since)
+        .await?;
+
+    let json_response = json!({
+        "feedback": feedback
+    });
+
+    Ok(Json(json_response))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::testing::{send, test_app, test_user};
+    use crate::rag::source::Source;
+    use crate::storage::model::{DBGeneration, DBMessageRole};
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+    use sqlx::PgPool;
+
+    #[sqlx::test(migrations = false)]
+    async fn test_feedback_and_export(pool: PgPool) {
+        let (app, state) = test_app(pool.clone()).await;
+        let (alice, alice_token) = test_user(&state, "alice@tcu.edu").await;
+        let (admin, admin_token) = test_user(&state, "advisor@tcu.edu").await;
+        sqlx::query("UPDATE chat.users SET is_admin = true WHERE id = $1")
+            .bind(admin.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let storage = &state.relational_storage;
+        let conversation = storage
+            .create_conversation(alice.id, "Degree plan".to_string())
+            .await
+            .unwrap();
+        let question = storage
+            .create_message(
+                conversation.id,
+                "When is the add deadline?".to_string(),
+                DBMessageRole::User,
+            )
+            .await
+            .unwrap();
+        let source = Source {
+            index: 1,
+            document: "calendar.pdf".to_string(),
+            chunk_id: "chunk-1".to_string(),
+            score: Some(0.8),
+            snippet: "Last day to add".to_string(),
+        };
+        let answer = storage
+            .create_answer(
+                conversation.id,
+                question.id,
+                "January 10th".to_string(),
+                &DBGeneration {
+                    model: "qwen".to_string(),
+                    prompt_tokens: Some(812),
+                    completion_tokens: Some(14),
+                    latency_ms: Some(950),
+                    template_version: "rag-1".to_string(),
+                    sources: vec![source],
+                    search_queries: vec![],
+                },
+                Some(question.id),
+            )
+            .await
+            .unwrap();
+
+        let uri = format!("/api/messages/{}/feedback", answer.id);
+        let down =
+            json!({"rating": "down", "category": "outdated", "comment": "It moved to the 17th"});
+
+        // questions cannot be rated, foreign answers are not found
+        let question_uri = format!("/api/messages/{}/feedback", question.id);
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            &question_uri,
+            &alice_token,
+            Some(down.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = send(&app, Method::POST, &uri, &admin_token, Some(down.clone())).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let up_with_category = json!({"rating": "up", "category": "unsafe"});
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            &uri,
+            &alice_token,
+            Some(up_with_category),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, body) = send(
+            &app,
+            Method::POST,
+            &uri,
+            &alice_token,
+            Some(json!({"rating": "up"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["feedback"]["rating"], "up");
+
+        // rating again replaces the earlier feedback
+        let (status, body) = send(&app, Method::POST, &uri, &alice_token, Some(down)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["feedback"]["category"], "outdated");
+
+        let (status, _) = send(&app, Method::GET, "/api/admin/feedback", &alice_token, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, body) = send(
+            &app,
+            Method::GET,
+            "/api/admin/feedback?rating=down",
+            &admin_token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let feedback = body["feedback"].as_array().unwrap();
+        assert_eq!(feedback.len(), 1);
+        assert_eq!(feedback[0]["prompt"], "When is the add deadline?");
+        assert_eq!(feedback[0]["answer"], "January 10th");
+        assert_eq!(feedback[0]["model"], "qwen");
+        assert_eq!(feedback[0]["sources"][0]["chunk_id"], "chunk-1");
+        assert_eq!(feedback[0]["comment"], "It moved to the 17th");
+
+        let (_, body) = send(
+            &app,
+            Method::GET,
+            "/api/admin/feedback?rating=up",
+            &admin_token,
+            None,
+        )
+        .await;
+        assert!(body["feedback"].as_array().unwrap().is_empty());
+    }
+}
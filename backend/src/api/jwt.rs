@@ -1,20 +1,22 @@
+use crate::api::error::AppError;
 use crate::app_state::AppState;
+use crate::storage::model::DBUser;
+use jsonwebtoken::errors::ErrorKind;
 use jsonwebtoken::{decode, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
 
 use axum::{
     extract::State,
-    http::{Request, StatusCode},
+    Extension,
+    http::Request,
     middleware::Next,
     response::IntoResponse,
-    Json,
 };
 use axum_extra::extract::CookieJar;
-use serde_json::json;
 use std::sync::Arc;
 use axum::body::Body;
-use axum::http::header;
-use tracing::{error, info};
+use axum::http::{header, HeaderMap};
+use tracing::info;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenClaims {
@@ -31,78 +33,64 @@ pub async fn auth(
     cookie_jar: CookieJar,
     mut req: Request<Body>,
     next: Next,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let token = cookie_jar
+) -> Result<impl IntoResponse, AppError> {
+    let token = request_token(&cookie_jar, req.headers()).ok_or(AppError::MissingToken)?;
+    let user = authenticate(&state, &token).await?;
+
+    req.extensions_mut().insert(user);
+    Ok(next.run(req).await)
+}
+
+/// The JWT sent with a request, from the `auth_token` cookie or the Authorization header.
+pub fn request_token(cookie_jar: &CookieJar, headers: &HeaderMap) -> Option<String> {
+    cookie_jar
         .get("auth_token")
         .map(|cookie| cookie.value().to_string())
         .or_else(|| {
-            req.headers()
+            headers
                 .get(header::AUTHORIZATION)
                 .and_then(|auth_header| auth_header.to_str().ok())
-                .and_then(|auth_val| {
-                    if auth_val.starts_with("Bearer ") {
-                        Some(auth_val[7..].to_owned())
-                    } else {
-                        None
-                    }
-                })
-        });
-
-    let token = match token {
-        Some(token) => token,
-        None => {
-            info!("No JWT found, please provide one");
-            let error_response = json!({
-                "message": "No JWT found, please provide one",
-            });
-            return Err((StatusCode::UNAUTHORIZED, Json(error_response)));
-        }
-    };
+                .and_then(|auth_val| auth_val.strip_prefix("Bearer ").map(|token| token.to_owned()))
+        })
+}
 
-    let claims = match decode::<TokenClaims>(
-        &token,
+/// Validates a JWT and loads the user it was issued for.
+pub async fn authenticate(state: &AppState, token: &str) -> Result<DBUser, AppError> {
+    let claims = decode::<TokenClaims>(
+        token,
         &DecodingKey::from_secret(state.config.jwt_secret.as_ref()),
         &Validation::default(),
-    ) {
-        Ok(token_data) => token_data.claims,
-        Err(e) => {
-            info!("Invalid JWT: {}", e);
-            return Err((
-                StatusCode::UNAUTHORIZED,
-                Json(json!({ "message": "Invalid or expired JWT" })),
-            ));
-        }
-    };
+    )?
+    .claims;
 
-    let user_id = match claims.sub.parse::<i32>() {
-        Ok(id) => id,
-        Err(e) => {
-            error!("Failed to parse user ID: {}", e);
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({ "message": "Invalid token format" })),
-            ));
-        }
-    };
+    let user_id = claims.sub.parse::<i32>().map_err(|e| {
+        info!("Failed to parse user ID: {}", e);
+        AppError::Jwt(ErrorKind::InvalidSubject.into())
+    })?;
 
-    let user = match state.relational_storage.get_user_by_id(&user_id).await {
-        Ok(Some(user)) => user,
-        Ok(None) => {
+    // the token outlived the user it was issued for
+    state
+        .relational_storage
+        .get_user_by_id(&user_id)
+        .await?
+        .ok_or_else(|| {
             info!("No user found for id: {}", user_id);
-            return Err((
-                StatusCode::UNAUTHORIZED,
-                Json(json!({ "message": "User not found" })),
-            ));
-        }
-        Err(e) => {
-            error!("Database error: {}", e);
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({ "message": "Failed to fetch user from database" })),
-            ));
-        }
-    };
+            AppError::Jwt(ErrorKind::InvalidSubject.into())
+        })
+}
+
+/// Rejects users without `is_admin`, must be layered inside the auth middleware.
+pub async fn admin(
+    Extension(user): Extension<DBUser>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<impl IntoResponse, AppError> {
+    if !user.is_admin {
+        info!("User {} denied access to {}", user.id, req.uri().path());
+        return Err(AppError::Forbidden(
+            "Administrator access required".to_string(),
+        ));
+    }
 
-    req.extensions_mut().insert(user);
     Ok(next.run(req).await)
-}
\ No newline at end of file
+}
@@ -0,0 +1,259 @@
+use crate::api::error::AppError;
+use crate::app_state::AppState;
+use crate::storage::model::{DBConversation, DBMessage, DBUser};
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Path};
+use axum::http::request::Parts;
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::{error, info};
+
+#[derive(Deserialize)]
+struct ConversationPath {
+    conversation_id: i32,
+}
+
+#[derive(Deserialize)]
+struct MessagePath {
+    message_id: i32,
+}
+
+/// The conversation named by the `:conversation_id` path parameter, extracting it rejects the
+/// request unless the conversation belongs to the authenticated user.
+/// Must only be used on routes behind the auth middleware.
+pub struct OwnedConversation(pub DBConversation);
+
+/// Like OwnedConversation, additionally extracts the message named by the `:message_id` path
+/// parameter, which must belong to the conversation.
+pub struct OwnedMessage(pub DBConversation, pub DBMessage);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for OwnedConversation {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let user = match parts.extensions.get::<DBUser>() {
+            Some(user) => user.clone(),
+            None => {
+                error!("OwnedConversation used on a route without the auth middleware");
+                return Err(AppError::MissingToken);
+            }
+        };
+
+        let Path(path) = Path::<ConversationPath>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| {
+                AppError::InvalidRequest(format!("Invalid conversation id: {}", e.body_text()))
+            })?;
+
+        find_owned_conversation(state, &user, path.conversation_id)
+            .await
+            .map(OwnedConversation)
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for OwnedMessage {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let OwnedConversation(conversation) =
+            OwnedConversation::from_request_parts(parts, state).await?;
+
+        let Path(path) = Path::<MessagePath>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| {
+                AppError::InvalidRequest(format!("Invalid message id: {}", e.body_text()))
+            })?;
+
+        match state
+            .relational_storage
+            .get_message_by_id(path.message_id)
+            .await?
+        {
+            Some(message) if message.conversation_id == conversation.id => {
+                Ok(OwnedMessage(conversation, message))
+            }
+            _ => Err(AppError::NotFound(format!(
+                "Message not found: {}",
+                path.message_id
+            ))),
+        }
+    }
+}
+
+/// Fetches a conversation and checks that `user` owns it.
+/// Conversations of other users are reported as not found, so their ids cannot be probed.
+pub async fn find_owned_conversation(
+    state: &AppState,
+    user: &DBUser,
+    conversation_id: i32,
+) -> Result<DBConversation, AppError> {
+    match state
+        .relational_storage
+        .get_conversation_by_id(conversation_id)
+        .await?
+    {
+        Some(conversation) if conversation.owner_id == user.id => Ok(conversation),
+        conversation => {
+            if conversation.is_some() {
+                info!(
+                    "User {} denied access to conversation {}",
+                    user.id, conversation_id
+                );
+            }
+            Err(AppError::NotFound(format!(
+                "Conversation not found: {}",
+                conversation_id
+            )))
+        }
+    }
+}
+
+/// Fetches a message and checks that `user` owns its conversation, for routes that name a
+/// message without its conversation. Foreign messages are reported as not found.
+pub async fn find_owned_message(
+    state: &AppState,
+    user: &DBUser,
+    message_id: i32,
+) -> Result<(DBConversation, DBMessage), AppError> {
+    let not_found = || AppError::NotFound(format!("Message not found: {}", message_id));
+
+    let message = state
+        .relational_storage
+        .get_message_by_id(message_id)
+        .await?
+        .ok_or_else(not_found)?;
+
+    let conversation = find_owned_conversation(state, user, message.conversation_id)
+        .await
+        .map_err(|e| match e {
+            AppError::NotFound(_) => not_found(),
+            e => e,
+        })?;
+
+    Ok((conversation, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::testing::{send, test_app, test_user};
+    use crate::storage::model::DBMessageRole;
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+    use sqlx::PgPool;
+
+    #[sqlx::test(migrations = false)]
+    async fn test_cross_user_access_is_not_found(pool: PgPool) {
+        let (app, state) = test_app(pool).await;
+        let (alice, alice_token) = test_user(&state, "alice@tcu.edu").await;
+        let (_, bob_token) = test_user(&state, "bob@tcu.edu").await;
+
+        let conversation = state
+            .relational_storage
+            .create_conversation(alice.id, "Degree plan".to_string())
+            .await
+            .unwrap();
+        state
+            .relational_storage
+            .create_message(conversation.id, "Hello".to_string(), DBMessageRole::User)
+            .await
+            .unwrap();
+
+        let messages_uri = format!("/api/conversations/{}/messages", conversation.id);
+        let completions_uri = format!("/api/conversations/{}/completions", conversation.id);
+        let message = json!({"content": "Injected", "role": "User"});
+
+        let (status, _) = send(&app, Method::GET, &messages_uri, &bob_token, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            &messages_uri,
+            &bob_token,
+            Some(message.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let rename = json!({"id": conversation.id, "title": "Renamed"});
+        let (status, _) = send(
+            &app,
+            Method::PUT,
+            "/api/conversations",
+            &bob_token,
+            Some(rename),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let completion = json!({"content": "Injected", "collection": "catalog"});
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            &completions_uri,
+            &bob_token,
+            Some(completion),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // nothing was written by the rejected requests
+        let (status, body) = send(&app, Method::GET, &messages_uri, &alice_token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["messages"].as_array().unwrap().len(), 1);
+
+        let conversation = state
+            .relational_storage
+            .get_conversation_by_id(conversation.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(conversation.title, "Degree plan");
+
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            &messages_uri,
+            &alice_token,
+            Some(message),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_unknown_and_invalid_conversation(pool: PgPool) {
+        let (app, state) = test_app(pool).await;
+        let (_, token) = test_user(&state, "alice@tcu.edu").await;
+
+        let (status, body) = send(
+            &app,
+            Method::GET,
+            "/api/conversations/999/messages",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["message"], "Conversation not found: 999");
+        assert_eq!(body["code"], "not_found");
+
+        let (status, _) = send(
+            &app,
+            Method::GET,
+            "/api/conversations/abc/messages",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
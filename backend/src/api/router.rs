@@ -1,14 +1,16 @@
 use std::sync::Arc;
 
-use axum::{middleware, routing::get, routing::post, Router};
-use crate::api::completions::{completion_new_handler, completion_new_title_handler, completion_streaming_handler};
-use crate::api::conversations::{conversation_list_handler, conversation_list_messages, conversation_new_handler, conversation_new_message_handler, conversation_update_handler};
+use axum::{middleware, routing::delete, routing::get, routing::post, routing::put, Router};
+use crate::api::completions::{completion_conversation_handler, completion_edit_handler, completion_new_handler, completion_regenerate_handler, completion_new_title_handler, completion_streaming_handler};
+use crate::api::conversations::{conversation_delete_handler, conversation_edit_handler, conversation_export_handler, conversation_list_handler, conversation_list_messages, conversation_message_siblings_handler, conversation_new_handler, conversation_new_message_handler, conversation_search_handler, conversation_select_branch_handler, conversation_update_handler};
+use crate::api::feedback::{admin_feedback_export_handler, message_feedback_handler};
 use crate::api::health::health_checker_handler;
+use crate::api::shares::{share_list_handler, share_new_handler, share_revoke_handler, shared_conversation_handler};
 use crate::app_state::AppState;
 use tower_http::trace::{self, TraceLayer};
 use tracing::Level;
 use crate::api::auth::{auth_login_handler, auth_logout_handler, auth_signup_handler};
-use crate::api::jwt::auth;
+use crate::api::jwt::{admin, auth};
 use crate::api::users::{user_get_handler, user_update_handler};
 
 pub fn create_router(app_state: Arc<AppState>) -> Router {
@@ -20,19 +22,58 @@ pub fn create_router(app_state: Arc<AppState>) -> Router {
         .route("/health", get(health_checker_handler))
         .route("/auth/signup", post(auth_signup_handler))
         .route("/auth/login", post(auth_login_handler))
-        .route("/auth/logout", get(auth_logout_handler));
+        .route("/auth/logout", get(auth_logout_handler))
+        .route("/shares/:token", get(shared_conversation_handler));
+
+    // Routes that additionally require an admin user
+    let admin_routes = Router::new()
+        .route("/admin/feedback", get(admin_feedback_export_handler))
+        .layer(middleware::from_fn(admin));
 
     // Protected routes that require authentication
     let protected_routes = Router::new()
         .route("/conversations", get(conversation_list_handler).post(conversation_new_handler).put(conversation_update_handler))
+        .route("/conversations/search", get(conversation_search_handler))
+        .route(
+            "/conversations/:conversation_id",
+            put(conversation_edit_handler).delete(conversation_delete_handler),
+        )
         .route(
             "/conversations/:conversation_id/messages",
             get(conversation_list_messages).post(conversation_new_message_handler),
         )
+        .route("/conversations/:conversation_id/completions", post(completion_conversation_handler))
+        .route("/conversations/:conversation_id/export", get(conversation_export_handler))
+        .route(
+            "/conversations/:conversation_id/shares",
+            get(share_list_handler).post(share_new_handler),
+        )
+        .route(
+            "/conversations/:conversation_id/shares/:share_id",
+            delete(share_revoke_handler),
+        )
+        .route(
+            "/conversations/:conversation_id/messages/:message_id/siblings",
+            get(conversation_message_siblings_handler),
+        )
+        .route(
+            "/conversations/:conversation_id/messages/:message_id/select",
+            post(conversation_select_branch_handler),
+        )
+        .route(
+            "/conversations/:conversation_id/messages/:message_id/regenerate",
+            post(completion_regenerate_handler),
+        )
+        .route(
+            "/conversations/:conversation_id/messages/:message_id/edit",
+            post(completion_edit_handler),
+        )
+        .route("/messages/:message_id/feedback", post(message_feedback_handler))
         .route("/completions", post(completion_new_handler))
         .route("/completions/stream", post(completion_streaming_handler))
         .route("/completions/title", post(completion_new_title_handler))
         .route("/users", get(user_get_handler).put(user_update_handler))
+        .merge(admin_routes)
         .layer(auth_layer);
 
     // Combine routes and add middleware
@@ -0,0 +1,331 @@
+use crate::api::error::AppError;
+use crate::api::jwt::{authenticate, request_token};
+use crate::api::ownership::OwnedConversation;
+use crate::app_state::AppState;
+use crate::rag::source::Source;
+use crate::storage::model::{DBMessageRole, DBSharedConversation, DBUser};
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use axum_extra::extract::{CookieJar, WithRejection};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+const DEFAULT_SHARE_DAYS: i64 = 30;
+const MAX_SHARE_DAYS: i64 = 365;
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CreateShareSchema {
+    // defaults to 30, at most 365
+    pub expires_in_days: Option<i64>,
+    // only let authenticated users of the owner's university view the share
+    pub same_university: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SharePath {
+    share_id: i32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TokenPath {
+    token: String,
+}
+
+/// The read-only view of a shared conversation, without anything identifying its owner.
+#[derive(Serialize, Debug)]
+pub struct SharedConversation {
+    pub title: String,
+    pub shared_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub messages: Vec<SharedMessage>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SharedMessage {
+    pub role: DBMessageRole,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub sources: Vec<Source>,
+}
+
+/// POST /api/conversations/{conversation_id}/shares -> JWT required
+/// Creates a share link for the active branch as it is now, later messages are not shared.
+pub async fn share_new_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<DBUser>,
+    OwnedConversation(conversation): OwnedConversation,
+    Json(payload): Json<CreateShareSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    let days = payload.expires_in_days.unwrap_or(DEFAULT_SHARE_DAYS);
+    if !(1..=MAX_SHARE_DAYS).contains(&days) {
+        return Err(AppError::InvalidRequest(format!(
+            "expires_in_days must be between 1 and {}",
+            MAX_SHARE_DAYS
+        )));
+    }
+
+    let same_university = payload.same_university.unwrap_or(false);
+    if same_university && university(&user).is_none() {
+        return Err(AppError::InvalidRequest(
+            "Set a university in your profile to restrict a share to it".to_string(),
+        ));
+    }
+
+    let token = Uuid::new_v4().simple().to_string();
+    let share = state
+        .relational_storage
+        .create_share(
+            conversation.id,
+            &token,
+            same_university,
+            Utc::now() + chrono::Duration::days(days),
+        )
+        .await?
+        .ok_or_else(|| {
+            AppError::InvalidRequest("A conversation without messages cannot be shared".to_string())
+        })?;
+
+    let json_response = json!({
+        "share": share
+    });
+
+    Ok((StatusCode::CREATED, Json(json_response)))
+}
+
+/// GET /api/conversations/{conversation_id}/shares -> JWT required
+/// Lists the shares of a conversation that were not revoked, including expired ones.
+pub async fn share_list_handler(
+    State(state): State<Arc<AppState>>,
+    OwnedConversation(conversation): OwnedConversation,
+) -> Result<impl IntoResponse, AppError> {
+    let shares = state
+        .relational_storage
+        .get_conversation_shares(conversation.id)
+        .await?;
+
+    let json_response = json!({
+        "shares": shares
+    });
+
+    Ok(Json(json_response))
+}
+
+/// DELETE /api/conversations/{conversation_id}/shares/{share_id} -> JWT required
+/// Revokes a share, its link stops working immediately.
+pub async fn share_revoke_handler(
+    State(state): State<Arc<AppState>>,
+    OwnedConversation(conversation): OwnedConversation,
+    WithRejection(Path(path), _): WithRejection<Path<SharePath>, AppError>,
+) -> Result<impl IntoResponse, AppError> {
+    let revoked = state
+        .relational_storage
+        .revoke_share(conversation.id, path.share_id)
+        .await?;
+
+    if !revoked {
+        return Err(AppError::NotFound(format!(
+            "Share not found: {}",
+            path.share_id
+        )));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/shares/{token} -> public, a JWT is only required for shares restricted to a university
+/// Shows the shared snapshot of a conversation.
+pub async fn shared_conversation_handler(
+    State(state): State<Arc<AppState>>,
+    cookie_jar: CookieJar,
+    headers: HeaderMap,
+    WithRejection(Path(path), _): WithRejection<Path<TokenPath>, AppError>,
+) -> Result<impl IntoResponse, AppError> {
+    // unknown, expired and revoked shares look the same
+    let share = state
+        .relational_storage
+        .get_shared_conversation(&path.token)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Share link not found or expired".to_string()))?;
+
+    if share.same_university {
+        let token = request_token(&cookie_jar, &headers).ok_or(AppError::MissingToken)?;
+        let viewer = authenticate(&state, &token).await?;
+        check_university(&share, &viewer)?;
+    }
+
+    let messages = state
+        .relational_storage
+        .get_branch(share.snapshot_leaf_id)
+        .await?
+        .into_iter()
+        .map(|message| SharedMessage {
+            role: message.role,
+            content: message.content,
+            created_at: message.created_at,
+            sources: message.sources.map(|sources| sources.0).unwrap_or_default(),
+        })
+        .collect();
+
+    let json_response = json!({
+        "conversation": SharedConversation {
+            title: share.title,
+            shared_at: share.created_at,
+            expires_at: share.expires_at,
+            messages,
+        }
+    });
+
+    Ok(Json(json_response))
+}
+
+fn university(user: &DBUser) -> Option<&str> {
+    user.university
+        .as_deref()
+        .map(str::trim)
+        .filter(|university| !university.is_empty())
+}
+
+fn check_university(share: &DBSharedConversation, viewer: &DBUser) -> Result<(), AppError> {
+    let owner_university = share
+        .owner_university
+        .as_deref()
+        .map(str::trim)
+        .filter(|university| !university.is_empty());
+
+    match (owner_university, university(viewer)) {
+        (Some(owner), Some(viewer)) if owner.eq_ignore_ascii_case(viewer) => Ok(()),
+        _ => {
+            info!("User {} denied access to a university share", viewer.id);
+            Err(AppError::Forbidden(
+                "This conversation is only shared with students of the same university".to_string(),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::testing::{send, test_app, test_user};
+    use crate::storage::model::DBMessageRole;
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+    use sqlx::PgPool;
+
+    #[sqlx::test(migrations = false)]
+    async fn test_share_lifecycle(pool: PgPool) {
+        let (app, state) = test_app(pool.clone()).await;
+        let (alice, alice_token) = test_user(&state, "alice@tcu.edu").await;
+        let (_, bob_token) = test_user(&state, "bob@tcu.edu").await;
+        let storage = &state.relational_storage;
+
+        let conversation = storage
+            .create_conversation(alice.id, "Degree plan".to_string())
+            .await
+            .unwrap();
+        let uri = format!("/api/conversations/{}/shares", conversation.id);
+
+        let (status, _) = send(&app, Method::POST, &uri, &alice_token, Some(json!({}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        storage
+            .create_message(conversation.id, "Hello".to_string(), DBMessageRole::User)
+            .await
+            .unwrap();
+
+        let (status, _) = send(&app, Method::POST, &uri, &bob_token, Some(json!({}))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, body) = send(&app, Method::POST, &uri, &alice_token, Some(json!({}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let share_id = body["share"]["id"].as_i64().unwrap();
+        let share_uri = format!("/api/shares/{}", body["share"]["token"].as_str().unwrap());
+
+        // messages after the share was created are not part of the snapshot
+        storage
+            .create_message(conversation.id, "Later".to_string(), DBMessageRole::User)
+            .await
+            .unwrap();
+
+        // no token is needed to view the share
+        let (status, body) = send(&app, Method::GET, &share_uri, "", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["conversation"]["title"], "Degree plan");
+        assert_eq!(
+            body["conversation"]["messages"].as_array().unwrap().len(),
+            1
+        );
+        assert!(body["conversation"].get("owner_id").is_none());
+
+        let (status, body) = send(&app, Method::GET, &uri, &alice_token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["shares"].as_array().unwrap().len(), 1);
+
+        let revoke_uri = format!("{}/{}", uri, share_id);
+        let (status, _) = send(&app, Method::DELETE, &revoke_uri, &alice_token, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send(&app, Method::DELETE, &revoke_uri, &alice_token, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = send(&app, Method::GET, &share_uri, "", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // expired shares are gone as well
+        let (_, body) = send(&app, Method::POST, &uri, &alice_token, Some(json!({}))).await;
+        sqlx::query("UPDATE chat.conversation_shares SET expires_at = now() - interval '1 minute' WHERE id = $1")
+            .bind(body["share"]["id"].as_i64().unwrap() as i32)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let share_uri = format!("/api/shares/{}", body["share"]["token"].as_str().unwrap());
+        let (status, _) = send(&app, Method::GET, &share_uri, "", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_same_university_share(pool: PgPool) {
+        let (app, state) = test_app(pool).await;
+        let storage = &state.relational_storage;
+        let (alice, alice_token) = test_user(&state, "alice@tcu.edu").await;
+        let (classmate, classmate_token) = test_user(&state, "carol@tcu.edu").await;
+        let (outsider, outsider_token) = test_user(&state, "dave@smu.edu").await;
+        for (user, university) in [(&alice, "TCU"), (&classmate, "tcu"), (&outsider, "SMU")] {
+            storage
+                .update_user(&user.id, &user.email, "", "", university)
+                .await
+                .unwrap();
+        }
+
+        let conversation = storage
+            .create_conversation(alice.id, "Degree plan".to_string())
+            .await
+            .unwrap();
+        storage
+            .create_message(conversation.id, "Hello".to_string(), DBMessageRole::User)
+            .await
+            .unwrap();
+
+        let uri = format!("/api/conversations/{}/shares", conversation.id);
+        let (_, body) = send(
+            &app,
+            Method::POST,
+            &uri,
+            &alice_token,
+            Some(json!({"same_university": true, "expires_in_days": 7})),
+        )
+        .await;
+        let share_uri = format!("/api/shares/{}", body["share"]["token"].as_str().unwrap());
+
+        let (status, _) = send(&app, Method::GET, &share_uri, "", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send(&app, Method::GET, &share_uri, &outsider_token, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send(&app, Method::GET, &share_uri, &classmate_token, None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,237 @@
+use crate::llm::inference::{InferenceError, InferenceResponse, InferenceStream};
+use crate::rag::source::Source;
+use axum::response::sse::Event;
+use futures_util::{stream, Stream, StreamExt};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::time::Instant;
+use tracing::error;
+
+/// Events sent by the streaming completion endpoints, in this order:
+///
+/// 1. `sources` - once, a JSON array of the sources placed in the prompt
+/// 2. `token` - any number, `{"content": "..."}` with the text generated since the last token
+/// 3. `done` - once on success, `{"model", "token_count", "prompt_token_count", "generation_time"}`
+///    plus `message_id` when the answer was stored in a conversation
+///    or `error` - once on failure, `{"code", "message"}`
+///
+/// Nothing is sent after `done` or `error`, a stream that ends without either was interrupted.
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum CompletionEvent {
+    Sources(Vec<Source>),
+    Token {
+        content: String,
+    },
+    Done {
+        model: String,
+        token_count: Option<u32>,
+        prompt_token_count: Option<u32>,
+        // milliseconds
+        generation_time: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message_id: Option<i32>,
+    },
+    Error {
+        code: CompletionErrorCode,
+        message: String,
+    },
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionErrorCode {
+    // the LLM backend failed after the stream started
+    InferenceFailed,
+    // the answer was generated but could not be stored
+    PersistenceFailed,
+}
+
+impl CompletionEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompletionEvent::Sources(_) => "sources",
+            CompletionEvent::Token { .. } => "token",
+            CompletionEvent::Done { .. } => "done",
+            CompletionEvent::Error { .. } => "error",
+        }
+    }
+
+    pub fn into_event(self) -> Event {
+        let name = self.name();
+
+        Event::default().event(name).json_data(&self).unwrap_or_else(|e| {
+            error!("Failed to serialize '{}' event: {}", name, e);
+            Event::default().event(name).data("null")
+        })
+    }
+}
+
+struct CompletionStreamState {
+    inference: InferenceStream,
+    model: String,
+    start: Instant,
+    token_count: Option<u32>,
+    prompt_token_count: Option<u32>,
+    generation_time: Option<u64>,
+    finished: bool,
+}
+
+impl CompletionStreamState {
+    fn record(&mut self, response: &InferenceResponse) {
+        self.token_count = response.token_count.or(self.token_count);
+        self.prompt_token_count = response.prompt_token_count.or(self.prompt_token_count);
+        self.generation_time = response.generation_time.or(self.generation_time);
+    }
+
+    fn done(&self) -> CompletionEvent {
+        CompletionEvent::Done {
+            model: self.model.clone(),
+            token_count: self.token_count,
+            prompt_token_count: self.prompt_token_count,
+            // fall back to wall-clock time for backends that do not report it
+            generation_time: self
+                .generation_time
+                .or_else(|| Some(self.start.elapsed().as_millis() as u64)),
+            message_id: None,
+        }
+    }
+
+    fn error(&self, e: InferenceError) -> CompletionEvent {
+        error!("Error in inference stream: {}", e);
+
+        CompletionEvent::Error {
+            code: CompletionErrorCode::InferenceFailed,
+            message: "The model failed while generating a response".to_string(),
+        }
+    }
+}
+
+/// Wraps an inference stream into the documented completion event protocol.
+pub fn completion_events(
+    sources: Vec<Source>,
+    inference: InferenceStream,
+    model: String,
+) -> impl Stream<Item = CompletionEvent> {
+    let state = CompletionStreamState {
+        inference,
+        model,
+        start: Instant::now(),
+        token_count: None,
+        prompt_token_count: None,
+        generation_time: None,
+        finished: false,
+    };
+
+    let events = stream::unfold(state, |mut state| async move {
+        if state.finished {
+            return None;
+        }
+
+        loop {
+            match state.inference.next().await {
+                Some(Ok(response)) => {
+                    state.record(&response);
+
+                    // backends emit empty chunks, e.g. the final chunk with only stats
+                    if !response.content.is_empty() {
+                        let event = CompletionEvent::Token {
+                            content: response.content,
+                        };
+                        return Some((event, state));
+                    }
+                }
+                Some(Err(e)) => {
+                    state.finished = true;
+                    let event = state.error(e);
+                    return Some((event, state));
+                }
+                None => {
+                    state.finished = true;
+                    let event = state.done();
+                    return Some((event, state));
+                }
+            }
+        }
+    });
+
+    stream::once(async { CompletionEvent::Sources(sources) }).chain(events)
+}
+
+pub fn into_sse_events(
+    events: impl Stream<Item = CompletionEvent>,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    events.map(|event| Ok(event.into_event()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::sse::{completion_events, CompletionErrorCode, CompletionEvent};
+    use crate::llm::inference::{InferenceError, InferenceResponse};
+    use futures_util::{stream, StreamExt};
+
+    fn response(content: &str, token_count: Option<u32>) -> Result<InferenceResponse, InferenceError> {
+        Ok(InferenceResponse {
+            content: content.to_string(),
+            token_count,
+            prompt_token_count: token_count.map(|_| 12),
+            generation_time: token_count.map(|_| 40),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_event_order() {
+        let inference = stream::iter(vec![
+            response("Hello", None),
+            response("", None),
+            response(" there", Some(2)),
+        ])
+        .boxed();
+
+        let events: Vec<CompletionEvent> = completion_events(vec![], inference, "qwen".to_string())
+            .collect()
+            .await;
+
+        let names: Vec<&str> = events.iter().map(|event| event.name()).collect();
+        assert_eq!(names, vec!["sources", "token", "token", "done"]);
+
+        match &events[3] {
+            CompletionEvent::Done {
+                model,
+                token_count,
+                prompt_token_count,
+                generation_time,
+                ..
+            } => {
+                assert_eq!(model, "qwen");
+                assert_eq!(*token_count, Some(2));
+                assert_eq!(*prompt_token_count, Some(12));
+                assert_eq!(*generation_time, Some(40));
+            }
+            event => panic!("expected done event, got {:?}", event),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_ends_stream() {
+        let inference = stream::iter(vec![
+            response("Hello", None),
+            Err(InferenceError::Message("connection reset".to_string())),
+            response(" there", Some(2)),
+        ])
+        .boxed();
+
+        let events: Vec<CompletionEvent> = completion_events(vec![], inference, "qwen".to_string())
+            .collect()
+            .await;
+
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            events[2],
+            CompletionEvent::Error {
+                code: CompletionErrorCode::InferenceFailed,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::api::jwt::TokenClaims;
+use crate::api::router::create_router;
+use crate::app_state::AppState;
+use crate::config::{Config, Environment, RetrievalConfig};
+use crate::llm::inference::LlmBackend;
+use crate::llm::mock::MockAdapter;
+use crate::llm::model::Model;
+use crate::storage::model::DBUser;
+use crate::storage::postgres::RelationalStorage;
+use crate::storage::vector::{
+    SearchMode, VectorDataPoint, VectorQuery, VectorQueryOptions, VectorStorage,
+    VectorStorageError, VectorStorageQueryResponse, VectorStorageResponse,
+};
+use crate::vectorization::embedding::EmbeddingError;
+use async_trait::async_trait;
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use axum::response::Response;
+use axum::Router;
+use fastembed::{Embedding, EmbeddingModel};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use sqlx::PgPool;
+use std::env::VarError;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const JWT_SECRET: &str = "test-secret";
+
+/// Returns the same catalog chunk for every query, so completions work without Qdrant.
+pub struct TestVectorStorage;
+
+#[async_trait]
+impl VectorStorage for TestVectorStorage {
+    async fn create_collection(
+        &self,
+        _: &str,
+        _: EmbeddingModel,
+        _: SearchMode,
+    ) -> Result<VectorStorageResponse, VectorStorageError> {
+        Ok(VectorStorageResponse { time: 0.0 })
+    }
+
+    async fn query(
+        &self,
+        collection_name: &str,
+        _: VectorQuery,
+        _: &VectorQueryOptions,
+    ) -> Result<VectorStorageQueryResponse, VectorStorageError> {
+        let point = VectorDataPoint {
+            uuid: "chunk-1".to_string(),
+            name: format!("{}.md_0", collection_name),
+            content: "MATH 10524 comes first.".to_string(),
+            embedding: None,
+            sparse_embedding: None,
+            score: Some(0.9),
+        };
+
+        Ok(VectorStorageQueryResponse {
+            time: 0.0,
+            points: vec![point],
+        })
+    }
+
+    async fn add_vectors(
+        &self,
+        _: &str,
+        _: Vec<VectorDataPoint>,
+    ) -> Result<VectorStorageResponse, VectorStorageError> {
+        Ok(VectorStorageResponse { time: 0.0 })
+    }
+}
+
+fn fixed_embedding(_: String) -> Result<Embedding, EmbeddingError> {
+    Ok(vec![0.0; 4])
+}
+
+/// Router backed by a fresh database from `#[sqlx::test(migrations = false)]`, the mock LLM and
+/// `TestVectorStorage`.
+pub async fn test_app(pool: PgPool) -> (Router, Arc<AppState>) {
+    test_app_with_llm(pool, MockAdapter::new(Model::Qwen)).await
+}
+
+/// Like `test_app` with a configured mock LLM, e.g. one that fails.
+pub async fn test_app_with_llm(pool: PgPool, llm: MockAdapter) -> (Router, Arc<AppState>) {
+    sqlx::raw_sql(include_str!("../../sql/creation_script.sql"))
+        .execute(&pool)
+        .await
+        .expect("Failed to apply creation_script.sql");
+
+    let config = Config {
+        deployment_url: "127.0.0.1:0".to_string(),
+        postgres_url: String::new(),
+        qdrant_url: "http://localhost:6334".to_string(),
+        qdrant_api_key: Err(VarError::NotPresent),
+        llm_backend: LlmBackend::Mock,
+        llm_model: Model::Qwen,
+        llm_context_window: 32768,
+        llm_completion_tokens: 4096,
+        ollama_url: String::new(),
+        ollama_port: 0,
+        openai_base_url: Err(VarError::NotPresent),
+        openai_api_key: Err(VarError::NotPresent),
+        mock_llm_template: Err(VarError::NotPresent),
+        mock_llm_latency_ms: 0,
+        mock_llm_failure: Err(VarError::NotPresent),
+        conversation_retention_days: 30,
+        semantic_search: false,
+        retrieval: RetrievalConfig::default(),
+        jwt_secret: JWT_SECRET.to_string(),
+        jwt_expired_in: 1,
+        jwt_max_age: 1,
+        environment: Environment::Development,
+    };
+
+    let state = Arc::new(AppState {
+        relational_storage: RelationalStorage::from_pool(pool),
+        vector_storage: Box::new(TestVectorStorage),
+        llm: Box::new(llm),
+        embedder: fixed_embedding,
+        config,
+    });
+
+    (create_router(state.clone()), state)
+}
+
+/// Creates a user and a valid JWT for it.
+pub async fn test_user(state: &AppState, email: &str) -> (DBUser, String) {
+    let user = state
+        .relational_storage
+        .create_user(email, "not-a-real-hash")
+        .await
+        .unwrap();
+
+    let now = chrono::Utc::now();
+    let claims = TokenClaims {
+        sub: user.id.to_string(),
+        iat: now.timestamp() as usize,
+        exp: (now + chrono::Duration::hours(1)).timestamp() as usize,
+    };
+
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(JWT_SECRET.as_ref()),
+    )
+    .unwrap();
+
+    (user, token)
+}
+
+/// Sends a request with a bearer token and returns the response as is, e.g. to read a stream.
+pub async fn request(
+    app: &Router,
+    method: Method,
+    uri: &str,
+    token: &str,
+    body: Option<serde_json::Value>,
+) -> Response {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+        .unwrap();
+
+    app.clone().oneshot(request).await.unwrap()
+}
+
+/// Sends a request with a bearer token and returns the status and the JSON body, if any.
+pub async fn send(
+    app: &Router,
+    method: Method,
+    uri: &str,
+    token: &str,
+    body: Option<serde_json::Value>,
+) -> (StatusCode, serde_json::Value) {
+    let response = request(app, method, uri, token, body).await;
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    (status, serde_json::from_slice(&bytes).unwrap_or_default())
+}
@@ -1,3 +1,4 @@
+use crate::api::error::AppError;
 use crate::app_state::AppState;
 use crate::storage::model::DBUser;
 use axum::extract::State;
@@ -7,7 +8,6 @@ use axum::{Extension, Json};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::sync::Arc;
-use tracing::{error, info};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct UserFilteredSchema {
@@ -29,30 +29,15 @@ pub struct UpdateUserSchema {
 
 /// GET /api/users/
 /// Authorized Endpoint -> JWT Required
-/// // TODO: generalize error message return (look at auth for this)
 pub async fn user_get_handler(
     State(state): State<Arc<AppState>>,
     Extension(user): Extension<DBUser>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let query_result = match state.relational_storage.get_user_by_id(&user.id).await {
-        Err(e) => {
-            // SQL error, failed to execute SQL SELECT
-            error!("Failed on SQL fetch: {}", e.to_string());
-            let error_response = json!({
-                "message": "Unable to fetch user details to a server error",
-            });
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
-        }
-        Ok(None) => {
-            // User does not exist with email
-            info!("User does not exist with id: {}", user.id);
-            let error_response = json!({
-                "message": "No user found",
-            });
-            return Err((StatusCode::NOT_FOUND, Json(error_response)));
-        }
-        Ok(Some(user)) => user,
-    };
+) -> Result<impl IntoResponse, AppError> {
+    let query_result = state
+        .relational_storage
+        .get_user_by_id(&user.id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("No user found".to_string()))?;
 
     // TODO: decide on a better way to handle None type values for User
     let filtered_user = UserFilteredSchema {
@@ -84,8 +69,8 @@ pub async fn user_update_handler(
     State(state): State<Arc<AppState>>,
     Extension(user): Extension<DBUser>,
     Json(payload): Json<UpdateUserSchema>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    match state
+) -> Result<impl IntoResponse, AppError> {
+    state
         .relational_storage
         .update_user(
             &user.id,
@@ -94,22 +79,10 @@ pub async fn user_update_handler(
             &payload.last_name,
             &payload.university,
         )
-        .await
-    {
-        Err(e) => {
-            // SQL error, failed to execute SQL SELECT
-            error!("Failed on SQL fetch: {}", e.to_string());
-            let error_response = json!({
-                "message": "Unable to update user due to a server error",
-            });
-            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)))
-        }
-        Ok(_) => {
-            // User does not exist with email
-            let json_response = json!({
-                "message": "Successfully updated user"
-            });
-            Ok((StatusCode::CREATED, Json(json_response)))
-        }
-    }
+        .await?;
+
+    let json_response = json!({
+        "message": "Successfully updated user"
+    });
+    Ok((StatusCode::CREATED, Json(json_response)))
 }
@@ -1,11 +1,14 @@
 use crate::config::Config;
-use crate::llm::ollama::OllamaAdapter;
+use crate::llm::inference::LlmProvider;
+use crate::rag::pipeline::Embedder;
 use crate::storage::postgres::RelationalStorage;
-use crate::storage::qdrant::QdrantAdapter;
+use crate::storage::vector::VectorStorage;
 
 pub struct AppState {
     pub relational_storage: RelationalStorage,
-    pub vector_storage: QdrantAdapter,
-    pub llm: OllamaAdapter,
+    pub vector_storage: Box<dyn VectorStorage>,
+    pub llm: Box<dyn LlmProvider>,
+    // embeds search queries, a fixed vector in tests since the model is not available there
+    pub embedder: Embedder,
     pub config: Config,
 }
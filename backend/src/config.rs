@@ -1,3 +1,10 @@
+use crate::llm::inference::LlmBackend;
+use crate::llm::model::Model;
+use crate::rag::diversity::MmrOptions;
+use crate::storage::vector::{SearchMode, VectorQueryOptions};
+use crate::vectorization::rerank::RerankOptions;
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::env::VarError;
 use std::fmt::{Display, Formatter};
 
@@ -25,6 +32,56 @@ impl Display for Environment {
     }
 }
 
+/// Vector search options, with overrides for single collections.
+#[derive(Debug, Clone, Default)]
+pub struct RetrievalConfig {
+    pub defaults: VectorQueryOptions,
+    pub collections: HashMap<String, CollectionRetrieval>,
+    // rerank the candidates of every collection with a cross-encoder, None when disabled
+    pub rerank: Option<RerankOptions>,
+    // select varied chunks by maximal marginal relevance, None when disabled
+    pub mmr: Option<MmrOptions>,
+    // let the LLM rewrite follow-up questions into standalone search queries
+    pub rewrite_queries: bool,
+}
+
+/// Retrieval options of one collection, unset options fall back to the defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CollectionRetrieval {
+    pub limit: Option<u64>,
+    pub score_threshold: Option<f32>,
+    pub sparse_score_threshold: Option<f32>,
+    pub offset: Option<u64>,
+    pub mode: Option<SearchMode>,
+}
+
+impl RetrievalConfig {
+    pub fn query_options(&self, collection: &str) -> VectorQueryOptions {
+        let mut options = self.defaults.clone();
+
+        if let Some(overrides) = self.collections.get(collection) {
+            if let Some(limit) = overrides.limit {
+                options.limit = limit;
+            }
+            if let Some(threshold) = overrides.score_threshold {
+                options.score_threshold = Some(threshold);
+            }
+            if let Some(threshold) = overrides.sparse_score_threshold {
+                options.sparse_score_threshold = Some(threshold);
+            }
+            if let Some(offset) = overrides.offset {
+                options.offset = offset;
+            }
+            if let Some(mode) = overrides.mode {
+                options.mode = mode;
+            }
+        }
+
+        options
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub deployment_url: String,
@@ -32,8 +89,26 @@ pub struct Config {
     pub qdrant_url: String,
     // not required for development environments
     pub qdrant_api_key: Result<String, VarError>,
+    pub llm_backend: LlmBackend,
+    pub llm_model: Model,
+    // tokens the model sees at once, split between the parts of a prompt by PromptBudget
+    pub llm_context_window: usize,
+    // tokens kept free for the answer, answers are cut off after this many tokens
+    pub llm_completion_tokens: usize,
     pub ollama_url: String,
     pub ollama_port: u16,
+    // only required when llm_backend is OpenAI
+    pub openai_base_url: Result<String, VarError>,
+    pub openai_api_key: Result<String, VarError>,
+    // only used when llm_backend is Mock
+    pub mock_llm_template: Result<String, VarError>,
+    pub mock_llm_latency_ms: u64,
+    pub mock_llm_failure: Result<String, VarError>,
+    // days a deleted conversation is kept before the retention job removes it
+    pub conversation_retention_days: i64,
+    // embed messages in the background and allow semantic conversation search
+    pub semantic_search: bool,
+    pub retrieval: RetrievalConfig,
     pub jwt_secret: String,
     pub jwt_expired_in: i64,
     pub jwt_max_age: i64,
@@ -52,10 +127,122 @@ impl Config {
         let postgres_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
         let qdrant_url = std::env::var("QDRANT_URL").expect("QDRANT_URL must be set");
         let qdrant_api_key = std::env::var("QDRANT_API_KEY");
+        let llm_backend = std::env::var("LLM_BACKEND")
+            .unwrap_or_else(|_| LlmBackend::Ollama.to_string())
+            .parse::<LlmBackend>()
+            .expect("Could not parse LLM_BACKEND, expected one of: ollama, openai, mock");
+        let llm_model = std::env::var("LLM_MODEL")
+            .unwrap_or_else(|_| Model::Qwen.to_string())
+            .parse::<Model>()
+            .expect("Could not parse LLM_MODEL as a known model");
+        let llm_context_window = std::env::var("LLM_CONTEXT_WINDOW")
+            .map(|window| window.parse::<usize>().expect("Could not parse LLM_CONTEXT_WINDOW as usize"))
+            .unwrap_or_else(|_| llm_model.context_window())
+            .min(llm_backend.max_context_window().unwrap_or(usize::MAX));
+        let llm_completion_tokens = std::env::var("LLM_COMPLETION_TOKENS")
+            .unwrap_or_else(|_| "4096".to_string())
+            .parse::<usize>()
+            .expect("Could not parse LLM_COMPLETION_TOKENS as usize");
         let ollama_url = std::env::var("OLLAMA_URL").expect("OLLAMA_URL must be set");
         let ollama_port = std::env::var("OLLAMA_PORT")
             .expect("OLLAMA_PORT must be set").parse::<u16>()
             .expect("Could not parse OLLAMA_PORT as u16");
+        let openai_base_url = std::env::var("OPENAI_BASE_URL");
+        let openai_api_key = std::env::var("OPENAI_API_KEY");
+        let mock_llm_template = std::env::var("MOCK_LLM_TEMPLATE");
+        let mock_llm_latency_ms = std::env::var("MOCK_LLM_LATENCY_MS")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse::<u64>()
+            .expect("Could not parse MOCK_LLM_LATENCY_MS as u64");
+        let mock_llm_failure = std::env::var("MOCK_LLM_FAILURE");
+        let conversation_retention_days = std::env::var("CONVERSATION_RETENTION_DAYS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse::<i64>()
+            .expect("Could not parse CONVERSATION_RETENTION_DAYS as i64");
+        let semantic_search = std::env::var("SEMANTIC_SEARCH")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .expect("Could not parse SEMANTIC_SEARCH as bool");
+        let mut retrieval_defaults = VectorQueryOptions::default().limit(
+            std::env::var("RETRIEVAL_LIMIT")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse::<u64>()
+                .expect("Could not parse RETRIEVAL_LIMIT as u64"),
+        );
+        if let Ok(threshold) = std::env::var("RETRIEVAL_SCORE_THRESHOLD") {
+            retrieval_defaults = retrieval_defaults.score_threshold(
+                threshold
+                    .parse::<f32>()
+                    .expect("Could not parse RETRIEVAL_SCORE_THRESHOLD as f32"),
+            );
+        }
+        if let Ok(threshold) = std::env::var("RETRIEVAL_SPARSE_SCORE_THRESHOLD") {
+            retrieval_defaults = retrieval_defaults.sparse_score_threshold(
+                threshold
+                    .parse::<f32>()
+                    .expect("Could not parse RETRIEVAL_SPARSE_SCORE_THRESHOLD as f32"),
+            );
+        }
+        retrieval_defaults = retrieval_defaults.offset(
+            std::env::var("RETRIEVAL_OFFSET")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse::<u64>()
+                .expect("Could not parse RETRIEVAL_OFFSET as u64"),
+        );
+        retrieval_defaults = retrieval_defaults.mode(
+            std::env::var("RETRIEVAL_MODE")
+                .unwrap_or_else(|_| "dense".to_string())
+                .parse::<SearchMode>()
+                .expect("Could not parse RETRIEVAL_MODE, expected one of: dense, hybrid"),
+        );
+        let retrieval_collections = std::env::var("RETRIEVAL_COLLECTIONS")
+            .map(|collections| {
+                serde_json::from_str::<HashMap<String, CollectionRetrieval>>(&collections)
+                    .expect("Could not parse RETRIEVAL_COLLECTIONS as a JSON object of collection options")
+            })
+            .unwrap_or_default();
+        let rerank = std::env::var("RERANK")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .expect("Could not parse RERANK as bool");
+        let rerank = rerank.then(|| {
+            RerankOptions::default()
+                .candidates(
+                    std::env::var("RERANK_CANDIDATES")
+                        .unwrap_or_else(|_| "20".to_string())
+                        .parse::<u64>()
+                        .expect("Could not parse RERANK_CANDIDATES as u64"),
+                )
+                .top_n(
+                    std::env::var("RERANK_TOP_N")
+                        .unwrap_or_else(|_| "5".to_string())
+                        .parse::<usize>()
+                        .expect("Could not parse RERANK_TOP_N as usize"),
+                )
+        });
+        let mmr = std::env::var("MMR")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .expect("Could not parse MMR as bool");
+        let mmr = mmr.then(|| {
+            MmrOptions::default()
+                .lambda(
+                    std::env::var("MMR_LAMBDA")
+                        .unwrap_or_else(|_| "0.7".to_string())
+                        .parse::<f32>()
+                        .expect("Could not parse MMR_LAMBDA as f32"),
+                )
+                .candidates(
+                    std::env::var("MMR_CANDIDATES")
+                        .unwrap_or_else(|_| "20".to_string())
+                        .parse::<u64>()
+                        .expect("Could not parse MMR_CANDIDATES as u64"),
+                )
+        });
+        let rewrite_queries = std::env::var("QUERY_REWRITING")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .expect("Could not parse QUERY_REWRITING as bool");
         let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
         let jwt_expired_in = std::env::var("JWT_EXPIRED_IN")
             .expect("JWT_EXPIRED_IN must be set").parse::<i64>()
@@ -69,12 +256,63 @@ impl Config {
             postgres_url,
             qdrant_url,
             qdrant_api_key,
+            llm_backend,
+            llm_model,
+            llm_context_window,
+            llm_completion_tokens,
             ollama_url,
             ollama_port,
+            openai_base_url,
+            openai_api_key,
+            mock_llm_template,
+            mock_llm_latency_ms,
+            mock_llm_failure,
+            conversation_retention_days,
+            semantic_search,
+            retrieval: RetrievalConfig {
+                defaults: retrieval_defaults,
+                collections: retrieval_collections,
+                rerank,
+                mmr,
+                rewrite_queries,
+            },
             jwt_secret,
             jwt_expired_in,
             jwt_max_age,
             environment: Environment::from_string(&env),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{CollectionRetrieval, RetrievalConfig};
+    use crate::storage::vector::{SearchMode, VectorQueryOptions};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_collection_query_options() {
+        let collections: HashMap<String, CollectionRetrieval> = serde_json::from_str(
+            r#"{"tcu": {"score_threshold": 0.6, "sparse_score_threshold": 4.0, "offset": 2, "mode": "hybrid"}}"#,
+        )
+        .unwrap();
+        let retrieval = RetrievalConfig {
+            defaults: VectorQueryOptions::default().limit(8).offset(1),
+            collections,
+            rerank: None,
+            mmr: None,
+            rewrite_queries: false,
+        };
+
+        let options = retrieval.query_options("tcu");
+        assert_eq!(options.limit, 8);
+        assert_eq!(options.score_threshold, Some(0.6));
+        assert_eq!(options.sparse_score_threshold, Some(4.0));
+        assert_eq!(options.offset, 2);
+        assert_eq!(options.mode, SearchMode::Hybrid);
+        let options = retrieval.query_options("smu");
+        assert_eq!(options.score_threshold, None);
+        assert_eq!(options.offset, 1);
+        assert_eq!(options.mode, SearchMode::Dense);
+    }
+}
@@ -0,0 +1,88 @@
+pub mod markdown;
+pub mod pdf;
+
+use crate::rag::source::Source;
+use crate::storage::model::{DBConversation, DBMessage, DBMessageRole};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Md,
+    Json,
+    Pdf,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Md => "text/markdown; charset=utf-8",
+            ExportFormat::Json => "application/json",
+            ExportFormat::Pdf => "application/pdf",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Md => "md",
+            ExportFormat::Json => "json",
+            ExportFormat::Pdf => "pdf",
+        }
+    }
+}
+
+/// A conversation as it is handed out of the application, the messages are its active branch.
+#[derive(Serialize, Debug)]
+pub struct ConversationExport {
+    pub conversation: DBConversation,
+    pub messages: Vec<DBMessage>,
+    pub exported_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("Failed to serialize conversation: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to render PDF: {0}")]
+    Pdf(String),
+}
+
+impl ConversationExport {
+    pub fn new(conversation: DBConversation, messages: Vec<DBMessage>) -> Self {
+        Self {
+            conversation,
+            messages,
+            exported_at: Utc::now(),
+        }
+    }
+
+    pub fn render(&self, format: ExportFormat) -> Result<Vec<u8>, ExportError> {
+        match format {
+            ExportFormat::Md => Ok(markdown::render(self).into_bytes()),
+            ExportFormat::Json => Ok(serde_json::to_vec_pretty(self)?),
+            ExportFormat::Pdf => pdf::render(self),
+        }
+    }
+}
+
+// labels used by the human-readable formats
+fn role_label(role: &DBMessageRole) -> &'static str {
+    match role {
+        DBMessageRole::User => "Student",
+        DBMessageRole::Assistant => "Echelon",
+    }
+}
+
+fn timestamp(time: &DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+fn sources(message: &DBMessage) -> &[Source] {
+    message
+        .sources
+        .as_ref()
+        .map_or(&[], |sources| sources.0.as_slice())
+}
@@ -0,0 +1,101 @@
+use crate::export::{role_label, sources, timestamp, ConversationExport};
+
+pub fn render(export: &ConversationExport) -> String {
+    let mut markdown = format!(
+        "# {}\n\nLast message {} · exported {}\n",
+        export.conversation.title,
+        timestamp(&export.conversation.last_message_at),
+        timestamp(&export.exported_at)
+    );
+
+    for message in &export.messages {
+        markdown.push_str(&format!(
+            "\n---\n\n**{}** · {}\n\n{}\n",
+            role_label(&message.role),
+            timestamp(&message.created_at),
+            message.content.trim()
+        ));
+
+        let sources = sources(message);
+        if !sources.is_empty() {
+            markdown.push_str("\nSources:\n\n");
+            for source in sources {
+                markdown.push_str(&format!("- [{}] {}\n", source.index, source.document));
+            }
+        }
+    }
+
+    markdown
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::markdown::render;
+    use crate::export::ConversationExport;
+    use crate::rag::source::Source;
+    use crate::storage::model::{DBConversation, DBConversationStatus, DBMessage, DBMessageRole};
+    use chrono::{DateTime, Utc};
+    use sqlx::types::Json;
+
+    fn message(
+        id: i32,
+        role: DBMessageRole,
+        content: &str,
+        sources: Option<Vec<Source>>,
+    ) -> DBMessage {
+        DBMessage {
+            id,
+            conversation_id: 1,
+            parent_id: None,
+            content: content.to_string(),
+            role,
+            created_at: DateTime::<Utc>::from_timestamp(1_740_000_000, 0).unwrap(),
+            model: None,
+            prompt_tokens: None,
+            completion_tokens: None,
+            latency_ms: None,
+            template_version: None,
+            sources: sources.map(Json),
+        }
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let conversation = DBConversation {
+            id: 1,
+            owner_id: 1,
+            title: "CS minor".to_string(),
+            title_generated: false,
+            last_message_at: DateTime::<Utc>::from_timestamp(1_740_000_060, 0).unwrap(),
+            status: DBConversationStatus::Active,
+        };
+        let source = Source {
+            index: 1,
+            document: "minors.pdf".to_string(),
+            chunk_id: "chunk-1".to_string(),
+            score: None,
+            snippet: String::new(),
+        };
+        let export = ConversationExport::new(
+            conversation,
+            vec![
+                message(1, DBMessageRole::User, "What does the minor require?", None),
+                message(
+                    2,
+                    DBMessageRole::Assistant,
+                    "18 hours [1]\n",
+                    Some(vec![source]),
+                ),
+            ],
+        );
+
+        let markdown = render(&export);
+
+        assert!(markdown.starts_with("# CS minor\n\nLast message 2025-02-19 21:21 UTC"));
+        assert!(markdown
+            .contains("**Student** · 2025-02-19 21:20 UTC\n\nWhat does the minor require?\n"));
+        assert!(markdown.contains(
+            "**Echelon** · 2025-02-19 21:20 UTC\n\n18 hours [1]\n\nSources:\n\n- [1] minors.pdf\n"
+        ));
+    }
+}
@@ -0,0 +1,220 @@
+use crate::export::{role_label, sources, timestamp, ConversationExport, ExportError};
+use printpdf::{
+    BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference,
+};
+
+// A4 in millimeters
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+const PT_TO_MM: f32 = 0.3528;
+// average glyph width of Helvetica relative to the font size, used to wrap lines
+const AVERAGE_CHAR_WIDTH: f32 = 0.5;
+
+const TITLE_SIZE: f32 = 16.0;
+const HEADER_SIZE: f32 = 11.0;
+const BODY_SIZE: f32 = 10.0;
+const SMALL_SIZE: f32 = 8.5;
+
+/// Renders the export with the built-in Helvetica fonts, so nothing has to be embedded or
+/// fetched. Characters outside of WinAnsi (Latin-1) cannot be shown by these fonts.
+pub fn render(export: &ConversationExport) -> Result<Vec<u8>, ExportError> {
+    let mut writer = PdfWriter::new(&export.conversation.title)?;
+
+    writer.paragraph(&export.conversation.title, TITLE_SIZE, true);
+    writer.paragraph(
+        &format!(
+            "Last message {} - exported {}",
+            timestamp(&export.conversation.last_message_at),
+            timestamp(&export.exported_at)
+        ),
+        SMALL_SIZE,
+        false,
+    );
+
+    for message in &export.messages {
+        writer.space(5.0);
+        writer.paragraph(
+            &format!(
+                "{} - {}",
+                role_label(&message.role),
+                timestamp(&message.created_at)
+            ),
+            HEADER_SIZE,
+            true,
+        );
+        writer.space(1.0);
+        writer.paragraph(message.content.trim(), BODY_SIZE, false);
+
+        let sources = sources(message);
+        if !sources.is_empty() {
+            writer.space(1.5);
+            writer.paragraph("Sources", SMALL_SIZE, true);
+            for source in sources {
+                writer.paragraph(
+                    &format!("[{}] {}", source.index, source.document),
+                    SMALL_SIZE,
+                    false,
+                );
+            }
+        }
+    }
+
+    writer.finish()
+}
+
+struct PdfWriter {
+    document: PdfDocumentReference,
+    layer: PdfLayerReference,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    // baseline of the next line, measured from the bottom of the page
+    y: f32,
+    pages: usize,
+}
+
+impl PdfWriter {
+    fn new(title: &str) -> Result<Self, ExportError> {
+        let (document, page, layer) =
+            PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Content");
+        let regular = document
+            .add_builtin_font(BuiltinFont::Helvetica)
+            .map_err(|e| ExportError::Pdf(e.to_string()))?;
+        let bold = document
+            .add_builtin_font(BuiltinFont::HelveticaBold)
+            .map_err(|e| ExportError::Pdf(e.to_string()))?;
+        let layer = document.get_page(page).get_layer(layer);
+
+        Ok(Self {
+            document,
+            layer,
+            regular,
+            bold,
+            y: PAGE_HEIGHT - MARGIN,
+            pages: 1,
+        })
+    }
+
+    /// Writes text wrapped to the page width, line breaks in the text are kept.
+    fn paragraph(&mut self, text: &str, size: f32, bold: bool) {
+        let max_chars =
+            ((PAGE_WIDTH - 2.0 * MARGIN) / (size * AVERAGE_CHAR_WIDTH * PT_TO_MM)) as usize;
+
+        for line in text.lines() {
+            let wrapped = wrap(line, max_chars);
+            if wrapped.is_empty() {
+                self.advance(size);
+            }
+            for line in wrapped {
+                self.line(&line, size, bold);
+            }
+        }
+    }
+
+    fn line(&mut self, text: &str, size: f32, bold: bool) {
+        self.advance(size);
+
+        let font = if bold { &self.bold } else { &self.regular };
+        self.layer
+            .use_text(text, size, Mm(MARGIN), Mm(self.y), font);
+    }
+
+    // moves down by one line of the given font size, starting a new page when it does not fit
+    fn advance(&mut self, size: f32) {
+        let height = size * 1.4 * PT_TO_MM;
+
+        if self.y - height < MARGIN {
+            let (page, layer) = self
+                .document
+                .add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Content");
+            self.layer = self.document.get_page(page).get_layer(layer);
+            self.y = PAGE_HEIGHT - MARGIN;
+            self.pages += 1;
+        }
+
+        self.y -= height;
+    }
+
+    fn space(&mut self, millimeters: f32) {
+        self.y -= millimeters;
+    }
+
+    fn finish(self) -> Result<Vec<u8>, ExportError> {
+        self.document
+            .save_to_bytes()
+            .map_err(|e| ExportError::Pdf(e.to_string()))
+    }
+}
+
+// Splits a line into lines of at most `max_chars` characters at spaces, words longer than a
+// line are split
+fn wrap(line: &str, max_chars: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut current = String::new();
+
+    for word in line.split_whitespace() {
+        let mut word = word.to_string();
+
+        while word.chars().count() > max_chars {
+            if !current.is_empty() {
+                lines.push(std::mem::take(&mut current));
+            }
+            let rest = word.chars().skip(max_chars).collect();
+            lines.push(word.chars().take(max_chars).collect());
+            word = rest;
+        }
+
+        if current.is_empty() {
+            current = word;
+        } else if current.chars().count() + 1 + word.chars().count() <= max_chars {
+            current.push(' ');
+            current.push_str(&word);
+        } else {
+            lines.push(std::mem::replace(&mut current, word));
+        }
+    }
+
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::pdf::{render, wrap, PdfWriter, BODY_SIZE};
+    use crate::export::ConversationExport;
+    use crate::storage::model::{DBConversation, DBConversationStatus};
+    use chrono::Utc;
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrap("one two three", 7), vec!["one two", "three"]);
+        assert_eq!(wrap("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        assert!(wrap("   ", 10).is_empty());
+    }
+
+    #[test]
+    fn test_pages_are_added() {
+        let mut writer = PdfWriter::new("Degree plan").unwrap();
+        writer.paragraph(
+            &"Which courses should I take next semester?\n".repeat(100),
+            BODY_SIZE,
+            false,
+        );
+        // 52 lines of body text fit on a page
+        assert_eq!(writer.pages, 2);
+
+        let conversation = DBConversation {
+            id: 1,
+            owner_id: 1,
+            title: "Degree plan".to_string(),
+            title_generated: false,
+            last_message_at: Utc::now(),
+            status: DBConversationStatus::Active,
+        };
+        let pdf = render(&ConversationExport::new(conversation, vec![])).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+}
@@ -0,0 +1,3 @@
+pub mod embeddings;
+pub mod retention;
+pub mod titles;
@@ -0,0 +1,73 @@
+use crate::app_state::AppState;
+use crate::storage::postgres::RelationalStorage;
+use crate::vectorization::embedding::{embed_batch, EmbeddingError};
+use fastembed::Embedding;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+// how often new messages are checked for
+const EMBEDDING_INTERVAL: Duration = Duration::from_secs(60);
+// messages embedded at once
+const EMBEDDING_BATCH_SIZE: i64 = 64;
+
+pub type BatchEmbedder = fn(&[String]) -> Result<Vec<Embedding>, EmbeddingError>;
+
+#[derive(Debug, Error)]
+pub enum EmbeddingJobError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error(transparent)]
+    Embedding(#[from] EmbeddingError),
+}
+
+/// Embeds every message that has no embedding yet, returns the number of embedded messages.
+pub async fn embed_pending(
+    storage: &RelationalStorage,
+    embedder: BatchEmbedder,
+) -> Result<usize, EmbeddingJobError> {
+    let mut embedded = 0;
+
+    loop {
+        let messages = storage
+            .get_unembedded_messages(EMBEDDING_BATCH_SIZE)
+            .await?;
+        if messages.is_empty() {
+            return Ok(embedded);
+        }
+
+        let (ids, contents): (Vec<i32>, Vec<String>) = messages.into_iter().unzip();
+
+        // embedding is CPU bound, keep it off the async workers
+        let embeddings = tokio::task::spawn_blocking(move || embedder(&contents))
+            .await
+            .map_err(|e| EmbeddingError::Message(format!("Embedding task failed: {}", e)))??;
+
+        let rows: Vec<(i32, Vec<f32>)> = ids.into_iter().zip(embeddings).collect();
+        storage.set_message_embeddings(&rows).await?;
+        embedded += rows.len();
+
+        if rows.len() < EMBEDDING_BATCH_SIZE as usize {
+            return Ok(embedded);
+        }
+    }
+}
+
+/// Runs `embed_pending` every minute for as long as the server is up.
+pub fn spawn(state: Arc<AppState>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EMBEDDING_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            match embed_pending(&state.relational_storage, embed_batch).await {
+                Ok(0) => {}
+                Ok(embedded) => info!("Embeddings job embedded {} messages", embedded),
+                Err(e) => error!("Embeddings job failed: {}", e),
+            }
+        }
+    })
+}
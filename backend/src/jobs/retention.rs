@@ -0,0 +1,95 @@
+use crate::app_state::AppState;
+use crate::storage::postgres::RelationalStorage;
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+// how often deleted conversations are checked for expiry
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Removes conversations that were soft-deleted more than `retention_days` ago.
+pub async fn purge_expired(
+    storage: &RelationalStorage,
+    retention_days: i64,
+) -> Result<u64, sqlx::Error> {
+    let deleted_before = Utc::now() - chrono::Duration::days(retention_days);
+
+    storage.purge_deleted_conversations(deleted_before).await
+}
+
+/// Runs `purge_expired` once per hour for as long as the server is up.
+pub fn spawn(state: Arc<AppState>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RETENTION_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            match purge_expired(
+                &state.relational_storage,
+                state.config.conversation_retention_days,
+            )
+            .await
+            {
+                Ok(0) => {}
+                Ok(purged) => info!("Retention job removed {} deleted conversations", purged),
+                Err(e) => error!("Retention job failed: {}", e),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::testing::{test_app, test_user};
+    use crate::jobs::retention::purge_expired;
+    use crate::storage::model::DBMessageRole;
+    use sqlx::PgPool;
+
+    #[sqlx::test(migrations = false)]
+    async fn test_purge_expired(pool: PgPool) {
+        let (_, state) = test_app(pool.clone()).await;
+        let (user, _) = test_user(&state, "alice@tcu.edu").await;
+        let storage = &state.relational_storage;
+
+        let mut ids = vec![];
+        for title in ["Expired", "Recently deleted", "Kept"] {
+            let conversation = storage
+                .create_conversation(user.id, title.to_string())
+                .await
+                .unwrap();
+            storage
+                .create_message(conversation.id, "Hello".to_string(), DBMessageRole::User)
+                .await
+                .unwrap();
+            ids.push(conversation.id);
+        }
+
+        storage.soft_delete_conversation(&ids[0]).await.unwrap();
+        storage.soft_delete_conversation(&ids[1]).await.unwrap();
+        sqlx::query(
+            "UPDATE chat.conversations SET deleted_at = now() - interval '31 days' WHERE id = $1",
+        )
+        .bind(ids[0])
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert_eq!(purge_expired(storage, 30).await.unwrap(), 1);
+
+        let remaining: Vec<i32> =
+            sqlx::query_scalar("SELECT id FROM chat.conversations ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(remaining, vec![ids[1], ids[2]]);
+
+        let messages: i64 = sqlx::query_scalar("SELECT count(*) FROM chat.messages")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(messages, 2);
+    }
+}
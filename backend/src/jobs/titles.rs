@@ -0,0 +1,224 @@
+use crate::api::completions::ApiMessage;
+use crate::app_state::AppState;
+use crate::llm::inference::{InferenceError, InferenceRequest};
+use crate::llm::prompt::{Instruction, Prompt};
+use crate::storage::model::DBMessageRole;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+/// Title of conversations created without one, it is replaced after the first exchange.
+pub const PLACEHOLDER_TITLE: &str = "Untitled";
+/// Longest title an owner can set, the column is a varchar(255).
+pub const MAX_TITLE_LENGTH: usize = 255;
+// generated titles are cut at a word boundary to fit the sidebar
+const MAX_GENERATED_TITLE_LENGTH: usize = 80;
+
+#[derive(Debug, Error)]
+pub enum TitleError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error(transparent)]
+    Inference(#[from] InferenceError),
+}
+
+/// Generates a title from the first exchange of the active branch and stores it, returns the
+/// stored title. Does nothing if the conversation has no answer yet, or if its title was
+/// generated before or set by the owner.
+pub async fn generate_title(
+    state: &AppState,
+    conversation_id: i32,
+) -> Result<Option<String>, TitleError> {
+    let storage = &state.relational_storage;
+
+    let Some(conversation) = storage.get_conversation_by_id(conversation_id).await? else {
+        return Ok(None);
+    };
+    if conversation.title_generated || conversation.title != PLACEHOLDER_TITLE {
+        return Ok(None);
+    }
+
+    let branch = storage.get_active_branch(conversation_id).await?;
+    let Some(answer) = branch
+        .iter()
+        .position(|message| matches!(message.role, DBMessageRole::Assistant))
+    else {
+        return Ok(None);
+    };
+    let history: Vec<ApiMessage> = branch
+        .into_iter()
+        .take(answer + 1)
+        .map(ApiMessage::from)
+        .collect();
+
+    // the question itself is better than the placeholder if the model returns nothing usable
+    let fallback = history
+        .iter()
+        .find(|message| matches!(message.role, DBMessageRole::User))
+        .and_then(|message| sanitize_title(&message.content));
+
+    let prompt = Prompt::new(history, None, None, None, Instruction::Title);
+    let completion = state.llm.generate(InferenceRequest::new(prompt)).await?;
+
+    let Some(title) = sanitize_title(&completion.content).or(fallback) else {
+        return Ok(None);
+    };
+
+    let stored = storage
+        .set_generated_title(conversation_id, PLACEHOLDER_TITLE, &title)
+        .await?;
+
+    Ok(stored.then_some(title))
+}
+
+/// Runs `generate_title` without blocking the request that stored the answer.
+pub fn spawn(state: Arc<AppState>, conversation_id: i32) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        match generate_title(&state, conversation_id).await {
+            Ok(Some(title)) => info!("Conversation {} titled '{}'", conversation_id, title),
+            Ok(None) => {}
+            Err(e) => error!(
+                "Failed to generate a title for conversation {}: {}",
+                conversation_id, e
+            ),
+        }
+    })
+}
+
+/// Turns a model completion into a single line title without markdown or quotes, cut to
+/// `MAX_GENERATED_TITLE_LENGTH` characters. `None` if nothing is left.
+pub fn sanitize_title(raw: &str) -> Option<String> {
+    let line = raw.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = strip_prefix_ignore_case(line, "title:").unwrap_or(line);
+
+    let cleaned: String = line
+        .chars()
+        .filter(|c| !matches!(c, '*' | '`' | '"' | '“' | '”'))
+        .collect();
+    let cleaned = cleaned
+        .trim_start_matches(['#', '\'', ' '])
+        .trim_end_matches(['.', '\'', ' ']);
+
+    let mut title = String::new();
+    for word in cleaned.split_whitespace() {
+        let length = title.chars().count();
+        if length > 0 && length + 1 + word.chars().count() > MAX_GENERATED_TITLE_LENGTH {
+            break;
+        }
+        if length > 0 {
+            title.push(' ');
+        }
+        title.push_str(word);
+    }
+
+    // a single word longer than the limit is cut instead
+    let title: String = title.chars().take(MAX_GENERATED_TITLE_LENGTH).collect();
+
+    (!title.is_empty()).then_some(title)
+}
+
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    text.get(..prefix.len())
+        .filter(|start| start.eq_ignore_ascii_case(prefix))
+        .map(|_| text[prefix.len()..].trim_start())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::testing::{test_app, test_user};
+    use crate::jobs::titles::{generate_title, sanitize_title, PLACEHOLDER_TITLE};
+    use crate::storage::model::DBMessageRole;
+    use sqlx::PgPool;
+
+    #[test]
+    fn test_sanitize_title() {
+        assert_eq!(
+            sanitize_title("\n**\"CS Minor Requirements\"**\nThis title covers..."),
+            Some("CS Minor Requirements".to_string())
+        );
+        assert_eq!(
+            sanitize_title("Title: ## `Spring   Schedule`."),
+            Some("Spring Schedule".to_string())
+        );
+        assert_eq!(sanitize_title("  \n ** \"\" "), None);
+
+        let long = "Requirements ".repeat(20);
+        let title = sanitize_title(&long).unwrap();
+        assert!(title.chars().count() <= 80);
+        assert!(title.ends_with("Requirements"));
+        assert_eq!(sanitize_title(&"a".repeat(100)).unwrap().len(), 80);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_generate_title(pool: PgPool) {
+        let (_, state) = test_app(pool).await;
+        let (user, _) = test_user(&state, "alice@tcu.edu").await;
+        let storage = &state.relational_storage;
+
+        let conversation = storage
+            .create_conversation(user.id, PLACEHOLDER_TITLE.to_string())
+            .await
+            .unwrap();
+        storage
+            .create_message(
+                conversation.id,
+                "Can I minor in CS?".to_string(),
+                DBMessageRole::User,
+            )
+            .await
+            .unwrap();
+
+        // nothing to title before the first answer
+        assert_eq!(generate_title(&state, conversation.id).await.unwrap(), None);
+
+        storage
+            .create_message(
+                conversation.id,
+                "Yes.".to_string(),
+                DBMessageRole::Assistant,
+            )
+            .await
+            .unwrap();
+        let title = generate_title(&state, conversation.id)
+            .await
+            .unwrap()
+            .unwrap();
+        let stored = storage
+            .get_conversation_by_id(conversation.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.title, title);
+        assert!(stored.title_generated);
+
+        // generated only once
+        assert_eq!(generate_title(&state, conversation.id).await.unwrap(), None);
+
+        // titles set by the owner are never replaced
+        let renamed = storage
+            .create_conversation(user.id, PLACEHOLDER_TITLE.to_string())
+            .await
+            .unwrap();
+        storage
+            .create_message(renamed.id, "Hello".to_string(), DBMessageRole::User)
+            .await
+            .unwrap();
+        storage
+            .create_message(renamed.id, "Hi!".to_string(), DBMessageRole::Assistant)
+            .await
+            .unwrap();
+        storage
+            .update_conversation(&renamed.id, Some("My plan"), None)
+            .await
+            .unwrap();
+        assert_eq!(generate_title(&state, renamed.id).await.unwrap(), None);
+        let stored = storage
+            .get_conversation_by_id(renamed.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.title, "My plan");
+        assert!(!stored.title_generated);
+    }
+}
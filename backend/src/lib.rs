@@ -1,6 +1,9 @@
 pub mod api;
+pub mod jobs;
 pub mod llm;
+pub mod rag;
 pub mod vectorization;
 pub mod storage;
 pub mod app_state;
 pub mod config;
+pub mod export;
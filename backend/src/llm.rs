@@ -1,4 +1,8 @@
+pub mod budget;
 pub mod inference;
+pub mod mock;
 pub mod model;
 pub mod ollama;
-pub mod prompt;
\ No newline at end of file
+pub mod openai;
+pub mod prompt;
+pub mod tokens;
\ No newline at end of file
@@ -0,0 +1,81 @@
+use crate::llm::model::Model;
+use crate::llm::tokens::TokenCounter;
+
+// shares of what is left after the completion allowance, in percent, the history gets the rest
+const INSTRUCTION_SHARE: usize = 6;
+const PROFILE_SHARE: usize = 11;
+const CONTEXT_SHARE: usize = 46;
+// the completion allowance never takes more than half of the context window
+const MAX_COMPLETION_SHARE: usize = 50;
+
+/// How many tokens each part of a RAG prompt may use, the parts add up to the context window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptBudget {
+    pub context_window: usize,
+    // kept free for the generated answer, also the most tokens an answer may have
+    pub completion: usize,
+    // instruction, question and the labels between the parts
+    pub instruction: usize,
+    pub profile: usize,
+    pub context: usize,
+    // summary of older messages and the recent messages kept verbatim
+    pub history: usize,
+    pub counter: TokenCounter,
+}
+
+impl PromptBudget {
+    pub fn new(model: &Model, context_window: usize, completion: usize) -> Self {
+        let completion = completion.min(context_window * MAX_COMPLETION_SHARE / 100);
+        let prompt = context_window - completion;
+        let share = |percent: usize| prompt * percent / 100;
+
+        let instruction = share(INSTRUCTION_SHARE);
+        let profile = share(PROFILE_SHARE);
+        let context = share(CONTEXT_SHARE);
+
+        Self {
+            context_window,
+            completion,
+            instruction,
+            profile,
+            context,
+            history: prompt - instruction - profile - context,
+            counter: TokenCounter::for_model(model),
+        }
+    }
+}
+
+impl Default for PromptBudget {
+    fn default() -> Self {
+        let model = Model::Qwen;
+
+        Self::new(&model, model.context_window(), 4096)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::llm::budget::PromptBudget;
+    use crate::llm::model::Model;
+
+    #[test]
+    fn test_budget_split() {
+        let budget = PromptBudget::new(&Model::Qwen, 32768, 4096);
+
+        assert_eq!(budget.completion, 4096);
+        assert_eq!(budget.context, 13189);
+        assert_eq!(
+            budget.completion
+                + budget.instruction
+                + budget.profile
+                + budget.context
+                + budget.history,
+            32768
+        );
+        assert!(budget.history > budget.profile);
+
+        // a completion allowance larger than the window would leave no room for the prompt
+        let budget = PromptBudget::new(&Model::Qwen, 4096, 8192);
+        assert_eq!(budget.completion, 2048);
+    }
+}
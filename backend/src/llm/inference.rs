@@ -1,15 +1,88 @@
+use crate::config::Config;
+use crate::llm::mock::{MockAdapter, MockFailure};
 use crate::llm::model::Model;
 use crate::llm::ollama::OllamaAdapter;
+use crate::llm::openai::OpenAIAdapter;
 use crate::llm::prompt::Prompt;
+use async_trait::async_trait;
+use futures_util::stream::BoxStream;
+use std::time::Duration;
+use strum_macros::{Display, EnumString};
 use thiserror::Error;
 
-pub fn build(model: Model, host: &str, port: u16) -> OllamaAdapter {
-    match model {
-        Model::Llama3_3b | Model::Qwen | Model::Phi4 | Model::BespokeMinicheck => {
-            OllamaAdapter::new(model, host, port)
+#[derive(Debug, PartialEq, EnumString, Display, Clone)]
+pub enum LlmBackend {
+    #[strum(serialize = "ollama")]
+    Ollama,
+    // any server implementing the OpenAI chat completions protocol (OpenAI, llama.cpp, vLLM, LocalAI)
+    #[strum(serialize = "openai")]
+    OpenAI,
+    // scripted responses for tests and offline development, never contacts a model server
+    #[strum(serialize = "mock")]
+    Mock,
+}
+
+impl LlmBackend {
+    /// The largest context window the backend can report token counts for, None if unlimited.
+    pub fn max_context_window(&self) -> Option<usize> {
+        match self {
+            // ollama-rs reads the token counts of a response as u16 and fails on larger ones
+            LlmBackend::Ollama => Some(u16::MAX as usize),
+            LlmBackend::OpenAI | LlmBackend::Mock => None,
+        }
+    }
+}
+
+// Stream of partial completions, each item holds the tokens generated since the previous item
+pub type InferenceStream = BoxStream<'static, Result<InferenceResponse, InferenceError>>;
+
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    fn model(&self) -> &Model;
+    async fn generate(
+        &self,
+        request: InferenceRequest,
+    ) -> Result<InferenceResponse, InferenceError>;
+    async fn generate_stream(
+        &self,
+        request: InferenceRequest,
+    ) -> Result<InferenceStream, InferenceError>;
+}
+
+pub fn build(config: &Config) -> Result<Box<dyn LlmProvider>, InferenceError> {
+    let model = config.llm_model.clone();
+
+    match config.llm_backend {
+        LlmBackend::Ollama => Ok(Box::new(OllamaAdapter::new(
+            model,
+            &config.ollama_url,
+            config.ollama_port,
+        ))),
+        LlmBackend::OpenAI => {
+            let base_url = config.openai_base_url.as_ref().map_err(|_| {
+                InferenceError::Message(
+                    "OPENAI_BASE_URL must be set when LLM_BACKEND is 'openai'".to_string(),
+                )
+            })?;
+            // local OpenAI-compatible servers usually accept any key
+            let api_key = config.openai_api_key.as_deref().unwrap_or("-");
+
+            Ok(Box::new(OpenAIAdapter::new(model, base_url, api_key)))
+        }
+        LlmBackend::Mock => {
+            let mut adapter = MockAdapter::new(model)
+                .latency(Duration::from_millis(config.mock_llm_latency_ms));
+
+            if let Ok(template) = &config.mock_llm_template {
+                adapter = adapter.template(template);
+            }
+
+            if let Ok(failure) = &config.mock_llm_failure {
+                adapter = adapter.failure(failure.parse::<MockFailure>()?);
+            }
+
+            Ok(Box::new(adapter))
         }
-        // default to Ollama
-        _ => OllamaAdapter::new(model, host, port),
     }
 }
 
@@ -32,6 +105,7 @@ impl InferenceRequest {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct InferenceOptions {
     // Maximum number of tokens to generate (Default: -1, infinite generation)
     pub max_tokens: Option<i32>,
@@ -73,7 +147,9 @@ pub struct InferenceResponse {
     // The response of the completion. This can be the entire completion or only a token if the completion is streaming.
     pub content: String,
     // Number of tokens in the generated content
-    pub token_count: Option<u16>,
+    pub token_count: Option<u32>,
+    // Number of tokens in the prompt, only known once the prompt has been evaluated
+    pub prompt_token_count: Option<u32>,
     // Generation time in milliseconds
     pub generation_time: Option<u64>,
 }
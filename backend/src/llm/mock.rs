@@ -0,0 +1,330 @@
+use crate::llm::inference::{
+    InferenceError, InferenceRequest, InferenceResponse, InferenceStream, LlmProvider,
+};
+use crate::llm::model::Model;
+use crate::llm::prompt::Instruction;
+use async_trait::async_trait;
+use futures_util::{stream, StreamExt};
+use std::str::FromStr;
+use std::time::Duration;
+
+const DEFAULT_TEMPLATE: &str = "You asked: {question}";
+const DEFAULT_TITLE: &str = "Mock Conversation";
+
+/// Where the mock backend should inject an error.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockFailure {
+    // generate and generate_stream fail before producing any output
+    Generate,
+    // the stream yields this many tokens and then an error
+    Stream { after_tokens: usize },
+}
+
+impl FromStr for MockFailure {
+    type Err = InferenceError;
+
+    // accepts 'generate' or 'stream:<tokens>'
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "generate" => Ok(MockFailure::Generate),
+            Some(("stream", tokens)) => tokens
+                .parse::<usize>()
+                .map(|after_tokens| MockFailure::Stream { after_tokens })
+                .map_err(|e| {
+                    InferenceError::Message(format!("Invalid token count for mock failure: {}", e))
+                }),
+            _ => Err(InferenceError::Message(format!(
+                "Unknown mock failure '{}', expected 'generate' or 'stream:<tokens>'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Deterministic LLM backend that never talks to a model server.
+///
+/// RAG completions are rendered from a template where `{question}`, `{context}`, `{profile}`
+/// and `{history}` are replaced with the matching parts of the prompt, title completions
+/// return a fixed title and summaries append `Summary of <n> messages` to the previous summary,
+/// separated by ` / `. Query rewrites return the question unless a rewrite is set. Streams split
+/// the completion on whitespace, one token per item.
+pub struct MockAdapter {
+    model: Model,
+    template: String,
+    title: String,
+    rewrite: Option<String>,
+    // delay before every generated token
+    latency: Duration,
+    failure: Option<MockFailure>,
+}
+
+impl MockAdapter {
+    pub fn new(model: Model) -> Self {
+        Self {
+            model,
+            template: DEFAULT_TEMPLATE.to_string(),
+            title: DEFAULT_TITLE.to_string(),
+            rewrite: None,
+            latency: Duration::ZERO,
+            failure: None,
+        }
+    }
+
+    pub fn template(mut self, template: &str) -> Self {
+        self.template = template.to_string();
+        self
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    pub fn rewrite(mut self, rewrite: &str) -> Self {
+        self.rewrite = Some(rewrite.to_string());
+        self
+    }
+
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn failure(mut self, failure: MockFailure) -> Self {
+        self.failure = Some(failure);
+        self
+    }
+
+    fn render(&self, request: InferenceRequest) -> String {
+        let prompt = request.prompt;
+
+        match prompt.instruction {
+            Instruction::Title => self.title.clone(),
+            Instruction::Rewrite => self
+                .rewrite
+                .clone()
+                .unwrap_or_else(|| prompt.question.unwrap_or_default()),
+            Instruction::Summary => {
+                let summary = format!("Summary of {} messages", prompt.history.len());
+
+                match prompt.summary {
+                    Some(previous) => format!("{} / {}", previous, summary),
+                    None => summary,
+                }
+            }
+            Instruction::RAG => {
+                let history = prompt
+                    .history
+                    .iter()
+                    .map(|message| format!("{}: {}", message.role, message.content))
+                    .collect::<Vec<String>>()
+                    .join("\n");
+
+                self.template
+                    .replace("{question}", prompt.question.as_deref().unwrap_or_default())
+                    .replace("{context}", prompt.context.as_deref().unwrap_or_default())
+                    .replace("{profile}", prompt.profile.as_deref().unwrap_or_default())
+                    .replace("{history}", &history)
+            }
+        }
+    }
+
+    // simulated generation time, derived from the latency so it stays deterministic
+    fn generation_time(&self, token_count: usize) -> u64 {
+        self.latency.as_millis() as u64 * token_count as u64
+    }
+}
+
+fn tokenize(content: &str) -> Vec<String> {
+    content.split_inclusive(' ').map(|token| token.to_string()).collect()
+}
+
+// whitespace-separated words in every part of the prompt
+fn count_prompt_tokens(request: &InferenceRequest) -> usize {
+    let prompt = &request.prompt;
+
+    let history = prompt
+        .history
+        .iter()
+        .map(|message| message.content.split_whitespace().count())
+        .sum::<usize>();
+
+    let parts = [&prompt.summary, &prompt.profile, &prompt.context, &prompt.question]
+        .into_iter()
+        .flatten()
+        .map(|part| part.split_whitespace().count())
+        .sum::<usize>();
+
+    history + parts
+}
+
+#[async_trait]
+impl LlmProvider for MockAdapter {
+    fn model(&self) -> &Model {
+        &self.model
+    }
+
+    async fn generate(
+        &self,
+        request: InferenceRequest,
+    ) -> Result<InferenceResponse, InferenceError> {
+        if self.failure == Some(MockFailure::Generate) {
+            return Err(InferenceError::Message("Injected mock generation failure".to_string()));
+        }
+
+        let prompt_token_count = count_prompt_tokens(&request);
+        let content = self.render(request);
+        let token_count = tokenize(&content).len();
+
+        tokio::time::sleep(self.latency * token_count as u32).await;
+
+        Ok(InferenceResponse {
+            content,
+            token_count: u32::try_from(token_count).ok(),
+            prompt_token_count: u32::try_from(prompt_token_count).ok(),
+            generation_time: Some(self.generation_time(token_count)),
+        })
+    }
+
+    async fn generate_stream(
+        &self,
+        request: InferenceRequest,
+    ) -> Result<InferenceStream, InferenceError> {
+        let fail_after = match self.failure {
+            Some(MockFailure::Generate) => {
+                return Err(InferenceError::Message(
+                    "Injected mock generation failure".to_string(),
+                ))
+            }
+            Some(MockFailure::Stream { after_tokens }) => Some(after_tokens),
+            None => None,
+        };
+
+        let prompt_token_count = u32::try_from(count_prompt_tokens(&request)).ok();
+        let tokens = tokenize(&self.render(request));
+        let token_count = tokens.len();
+        let generation_time = self.generation_time(token_count);
+        let latency = self.latency;
+
+        let items = tokens
+            .into_iter()
+            .enumerate()
+            .map(move |(idx, token)| {
+                if fail_after == Some(idx) {
+                    return Err(InferenceError::Message(
+                        "Injected mock stream failure".to_string(),
+                    ));
+                }
+
+                // like Ollama, only the final item carries the generation stats
+                let is_last = idx + 1 == token_count;
+                Ok(InferenceResponse {
+                    content: token,
+                    token_count: is_last.then(|| u32::try_from(token_count).ok()).flatten(),
+                    prompt_token_count: is_last.then_some(prompt_token_count).flatten(),
+                    generation_time: is_last.then_some(generation_time),
+                })
+            })
+            .take(fail_after.map_or(token_count, |after| after + 1));
+
+        Ok(stream::iter(items)
+            .then(move |item| async move {
+                tokio::time::sleep(latency).await;
+                item
+            })
+            .boxed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::completions::ApiMessage;
+    use crate::llm::inference::{InferenceRequest, LlmProvider};
+    use crate::llm::mock::{MockAdapter, MockFailure};
+    use crate::llm::model::Model;
+    use crate::llm::prompt::{Instruction, Prompt};
+    use crate::storage::model::DBMessageRole;
+    use futures_util::StreamExt;
+    use std::time::Duration;
+
+    fn rag_request(question: &str) -> InferenceRequest {
+        InferenceRequest::new(Prompt::new(
+            vec![ApiMessage {
+                role: DBMessageRole::User,
+                content: question.to_string(),
+            }],
+            Some("Junior, BS Computer Science".to_string()),
+            Some("COSC 30603 requires COSC 20203".to_string()),
+            Some(question.to_string()),
+            Instruction::RAG,
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_generate_renders_template() {
+        let llm = MockAdapter::new(Model::Qwen).template("{question} | {context} | {profile}");
+
+        let response = llm.generate(rag_request("What are the prerequisites?")).await.unwrap();
+
+        assert_eq!(
+            response.content,
+            "What are the prerequisites? | COSC 30603 requires COSC 20203 | Junior, BS Computer Science"
+        );
+        assert_eq!(response.token_count, Some(15));
+        assert_eq!(response.prompt_token_count, Some(17));
+    }
+
+    #[tokio::test]
+    async fn test_generate_title() {
+        let llm = MockAdapter::new(Model::Qwen).title("CS Minor Requirements");
+        let prompt = Prompt::new(vec![], None, None, None, Instruction::Title);
+
+        let response = llm.generate(InferenceRequest::new(prompt)).await.unwrap();
+
+        assert_eq!(response.content, "CS Minor Requirements");
+    }
+
+    #[tokio::test]
+    async fn test_stream_matches_generate() {
+        let llm = MockAdapter::new(Model::Qwen).latency(Duration::from_millis(1));
+
+        let completion = llm.generate(rag_request("Can I minor in math?")).await.unwrap();
+        let items: Vec<_> = llm
+            .generate_stream(rag_request("Can I minor in math?"))
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        let streamed = items
+            .iter()
+            .map(|item| item.as_ref().unwrap().content.as_str())
+            .collect::<String>();
+
+        assert_eq!(streamed, completion.content);
+        assert_eq!(items.len(), 7);
+
+        let last = items.last().unwrap().as_ref().unwrap();
+        assert_eq!(last.token_count, Some(7));
+        assert_eq!(last.generation_time, Some(7));
+    }
+
+    #[tokio::test]
+    async fn test_injected_failures() {
+        let llm = MockAdapter::new(Model::Qwen).failure(MockFailure::Generate);
+        assert!(llm.generate(rag_request("Hello")).await.is_err());
+        assert!(llm.generate_stream(rag_request("Hello")).await.is_err());
+
+        let llm = MockAdapter::new(Model::Qwen).failure("stream:2".parse().unwrap());
+        let items: Vec<_> = llm
+            .generate_stream(rag_request("Which courses count toward the core?"))
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert_eq!(items.len(), 3);
+        assert!(items[0].is_ok() && items[1].is_ok());
+        assert!(items[2].is_err());
+    }
+}
@@ -22,4 +22,29 @@ pub enum Model {
     Llama3,
     #[strum(serialize = "command-a:111b-03-2025-q4_K_M")]
     CommandA,
+    // any other model name, passed to the backend verbatim (e.g. a model served by vLLM)
+    #[strum(default, to_string = "{0}")]
+    Custom(String),
+}
+
+impl Model {
+    /// Context window used when LLM_CONTEXT_WINDOW is not set. Models served locally get the
+    /// 32768 tokens Ollama always ran with, or less if they were trained with less, since a larger
+    /// window grows the KV cache on the GPU. Unknown models get a small window that every current
+    /// model supports.
+    pub fn context_window(&self) -> usize {
+        match self {
+            Model::Qwen
+            | Model::BespokeMinicheck
+            | Model::Mistral24b
+            | Model::Llama3_3b
+            | Model::Llama3
+            | Model::Gemma3
+            | Model::MistralSmall
+            | Model::CommandA => 32_768,
+            Model::Phi4 => 16_384,
+            Model::GPT4o => 128_000,
+            Model::Custom(_) => 8_192,
+        }
+    }
 }
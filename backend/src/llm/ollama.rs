@@ -1,7 +1,10 @@
 use crate::llm::inference::{
-    InferenceError, InferenceOptions, InferenceRequest, InferenceResponse,
+    InferenceError, InferenceOptions, InferenceRequest, InferenceResponse, InferenceStream,
+    LlmProvider,
 };
 use crate::llm::model::Model;
+use async_trait::async_trait;
+use futures_util::StreamExt;
 use ollama_rs::generation::completion::request::GenerationRequest;
 use ollama_rs::generation::completion::GenerationResponse;
 use ollama_rs::generation::options::GenerationOptions;
@@ -10,7 +13,7 @@ use std::default::Default;
 use crate::llm::prompt::Instruction;
 
 pub struct OllamaAdapter {
-    pub client: Ollama,
+    client: Ollama,
     model: Model,
 }
 
@@ -23,47 +26,96 @@ impl OllamaAdapter {
             model,
         }
     }
+}
 
-    pub fn model(&self) -> &Model {
+#[async_trait]
+impl LlmProvider for OllamaAdapter {
+    fn model(&self) -> &Model {
         &self.model
     }
 
-    pub(crate) async fn generate(
+    async fn generate(
         &self,
         request: InferenceRequest,
     ) -> Result<InferenceResponse, InferenceError> {
-        let generation_request = request
-            .to_generation_request(self.model())
-            .options(InferenceOptions::default().into());
+        let generation_request = request.into_generation_request(self.model());
 
         match self.client.generate(generation_request).await {
             Ok(response) => Ok(InferenceResponse::new(response)),
             Err(err) => Err(InferenceError::Message(err.to_string())),
         }
     }
+
+    async fn generate_stream(
+        &self,
+        request: InferenceRequest,
+    ) -> Result<InferenceStream, InferenceError> {
+        let generation_request = request.into_generation_request(self.model());
+
+        match self.client.generate_stream(generation_request).await {
+            Ok(stream) => Ok(stream
+                .map(|chunk| match chunk {
+                    Ok(responses) => Ok(InferenceResponse::from_chunk(responses)),
+                    Err(err) => Err(InferenceError::Message(err.to_string())),
+                })
+                .boxed()),
+            Err(err) => Err(InferenceError::Message(err.to_string())),
+        }
+    }
 }
 
 impl InferenceRequest {
-    fn to_generation_request(self, model: &Model) -> GenerationRequest {
+    fn into_generation_request(self, model: &Model) -> GenerationRequest<'static> {
         let prompt = match self.prompt.instruction {
             Instruction::RAG => self.prompt.to_string_rag(),
-            Instruction::Title => self.prompt.to_string_title()
+            Instruction::Title => self.prompt.to_string_title(),
+            Instruction::Summary => self.prompt.to_string_summary(),
+            Instruction::Rewrite => self.prompt.to_string_rewrite(),
         };
 
         GenerationRequest::new(model.to_string(), prompt)
+            .options(self.options.unwrap_or_default().into())
     }
 }
 
 impl InferenceResponse {
+    // ollama-rs reads the eval counts as u16, responses with more tokens fail to deserialize, which
+    // the context window is clamped to avoid, see LlmBackend::max_context_window
     fn new(response: GenerationResponse) -> InferenceResponse {
         let generation_time = response.eval_duration.map(|duration| duration / 1_000_000);
 
         Self {
             content: response.response,
-            token_count: response.eval_count,
+            token_count: response.eval_count.map(u32::from),
+            prompt_token_count: response.prompt_eval_count.map(u32::from),
             generation_time,
         }
     }
+
+    // Ollama can batch several responses into one chunk, only the final response carries eval stats
+    fn from_chunk(responses: Vec<GenerationResponse>) -> InferenceResponse {
+        let content = responses
+            .iter()
+            .map(|response| response.response.as_str())
+            .collect::<String>();
+
+        let final_response = responses.into_iter().rfind(|response| response.done);
+
+        Self {
+            content,
+            token_count: final_response
+                .as_ref()
+                .and_then(|response| response.eval_count)
+                .map(u32::from),
+            prompt_token_count: final_response
+                .as_ref()
+                .and_then(|response| response.prompt_eval_count)
+                .map(u32::from),
+            generation_time: final_response
+                .and_then(|response| response.eval_duration)
+                .map(|duration| duration / 1_000_000),
+        }
+    }
 }
 
 impl From<InferenceOptions> for GenerationOptions {
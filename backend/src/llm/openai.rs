@@ -0,0 +1,319 @@
+use crate::llm::inference::{
+    InferenceError, InferenceRequest, InferenceResponse, InferenceStream,
+    LlmProvider,
+};
+use crate::llm::model::Model;
+use crate::llm::prompt::Instruction;
+use async_trait::async_trait;
+use futures_util::stream::BoxStream;
+use futures_util::{stream, StreamExt};
+use openai_api_rs::v1::api::OpenAIClient;
+use openai_api_rs::v1::chat_completion::{
+    ChatCompletionMessage, ChatCompletionRequest, ChatCompletionResponse, Content, MessageRole,
+};
+use openai_api_rs::v1::common::Usage;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// Speaks the `/v1/chat/completions` protocol, so it works against OpenAI as well as
+/// OpenAI-compatible servers such as llama.cpp, vLLM and LocalAI.
+pub struct OpenAIAdapter {
+    http: reqwest::Client,
+    // base URL including the API version, e.g. http://localhost:8080/v1
+    base_url: String,
+    api_key: String,
+    model: Model,
+}
+
+impl OpenAIAdapter {
+    pub fn new(model: Model, base_url: &str, api_key: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            model,
+        }
+    }
+
+    // openai-api-rs requires a mutable client per call, building one is cheap
+    fn client(&self) -> Result<OpenAIClient, InferenceError> {
+        OpenAIClient::builder()
+            .with_endpoint(&self.base_url)
+            .with_api_key(&self.api_key)
+            .build()
+            .map_err(|e| {
+                InferenceError::Message(format!("Could not create OpenAI client: {}", e))
+            })
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAIAdapter {
+    fn model(&self) -> &Model {
+        &self.model
+    }
+
+    async fn generate(
+        &self,
+        request: InferenceRequest,
+    ) -> Result<InferenceResponse, InferenceError> {
+        let chat_request = request.into_chat_completion_request(self.model());
+        let mut client = self.client()?;
+        let start = Instant::now();
+
+        match client.chat_completion(chat_request).await {
+            Ok(response) => Ok(InferenceResponse::from_chat_completion(
+                response,
+                start.elapsed().as_millis() as u64,
+            )),
+            Err(err) => Err(InferenceError::Message(err.to_string())),
+        }
+    }
+
+    async fn generate_stream(
+        &self,
+        request: InferenceRequest,
+    ) -> Result<InferenceStream, InferenceError> {
+        let chat_request = StreamingChatCompletionRequest {
+            request: request
+                .into_chat_completion_request(self.model())
+                .stream(true),
+            // without it the token counts are never sent while streaming
+            stream_options: StreamOptions {
+                include_usage: true,
+            },
+        };
+        let start = Instant::now();
+
+        let response = self
+            .http
+            .post(format!("{}/chat/completions", self.base_url))
+            .bearer_auth(&self.api_key)
+            .json(&chat_request)
+            .send()
+            .await
+            .map_err(|e| InferenceError::Message(format!("Chat completion request failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(InferenceError::Message(format!(
+                "Chat completion request failed with status {}: {}",
+                status, body
+            )));
+        }
+
+        let state = ChunkStreamState {
+            bytes: response
+                .bytes_stream()
+                .map(|chunk| chunk.map(|bytes| bytes.to_vec()))
+                .boxed(),
+            buffer: Vec::new(),
+            start,
+        };
+
+        Ok(stream::unfold(state, next_chunk).boxed())
+    }
+}
+
+impl InferenceRequest {
+    fn into_chat_completion_request(self, model: &Model) -> ChatCompletionRequest {
+        let prompt = match self.prompt.instruction {
+            Instruction::RAG => self.prompt.to_string_rag(),
+            Instruction::Title => self.prompt.to_string_title(),
+            Instruction::Summary => self.prompt.to_string_summary(),
+            Instruction::Rewrite => self.prompt.to_string_rewrite(),
+        };
+
+        let message = ChatCompletionMessage {
+            role: MessageRole::user,
+            content: Content::Text(prompt),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        };
+
+        let options = self.options.unwrap_or_default();
+        let request = ChatCompletionRequest::new(model.to_string(), vec![message]);
+        let request = match options.temperature {
+            Some(temperature) => request.temperature(temperature as f64),
+            None => request,
+        };
+
+        // the OpenAI protocol has no "infinite generation" value, so negative limits are left unset
+        match options.max_tokens {
+            Some(max_tokens) if max_tokens > 0 => request.max_tokens(max_tokens as i64),
+            _ => request,
+        }
+    }
+}
+
+impl InferenceResponse {
+    fn from_chat_completion(response: ChatCompletionResponse, elapsed: u64) -> InferenceResponse {
+        let content = response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .unwrap_or_default();
+
+        Self {
+            content,
+            token_count: u32::try_from(response.usage.completion_tokens).ok(),
+            prompt_token_count: u32::try_from(response.usage.prompt_tokens).ok(),
+            generation_time: Some(elapsed),
+        }
+    }
+
+    fn from_chat_completion_chunk(chunk: ChatCompletionChunk, start: &Instant) -> InferenceResponse {
+        let finished = chunk
+            .choices
+            .iter()
+            .any(|choice| choice.finish_reason.is_some())
+            || chunk.usage.is_some();
+
+        let content = chunk
+            .choices
+            .into_iter()
+            .filter_map(|choice| choice.delta.content)
+            .collect::<String>();
+
+        Self {
+            content,
+            token_count: chunk
+                .usage
+                .as_ref()
+                .and_then(|usage| u32::try_from(usage.completion_tokens).ok()),
+            prompt_token_count: chunk
+                .usage
+                .and_then(|usage| u32::try_from(usage.prompt_tokens).ok()),
+            generation_time: finished.then(|| start.elapsed().as_millis() as u64),
+        }
+    }
+}
+
+// openai-api-rs has no `stream_options`, usage then comes in a last chunk without choices
+#[derive(Debug, Serialize)]
+struct StreamingChatCompletionRequest {
+    #[serde(flatten)]
+    request: ChatCompletionRequest,
+    stream_options: StreamOptions,
+}
+
+#[derive(Debug, Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionChunk {
+    choices: Vec<ChatCompletionChunkChoice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionChunkChoice {
+    delta: ChatCompletionDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionDelta {
+    content: Option<String>,
+}
+
+struct ChunkStreamState {
+    bytes: BoxStream<'static, reqwest::Result<Vec<u8>>>,
+    // holds bytes of a server-sent event line that has not been terminated yet
+    buffer: Vec<u8>,
+    start: Instant,
+}
+
+// Reads server-sent event lines until the next `data:` payload, `data: [DONE]` ends the stream
+async fn next_chunk(
+    mut state: ChunkStreamState,
+) -> Option<(Result<InferenceResponse, InferenceError>, ChunkStreamState)> {
+    loop {
+        if let Some(idx) = state.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = state.buffer.drain(..=idx).collect();
+            let line = String::from_utf8_lossy(&line);
+
+            let data = match line.trim().strip_prefix("data:") {
+                Some(data) => data.trim(),
+                // comments, event names and keep-alive blank lines
+                None => continue,
+            };
+
+            if data == "[DONE]" {
+                return None;
+            }
+
+            let response = serde_json::from_str::<ChatCompletionChunk>(data)
+                .map(|chunk| InferenceResponse::from_chat_completion_chunk(chunk, &state.start))
+                .map_err(|e| {
+                    InferenceError::Message(format!("Could not parse completion chunk: {}", e))
+                });
+
+            return Some((response, state));
+        }
+
+        match state.bytes.next().await {
+            Some(Ok(bytes)) => state.buffer.extend_from_slice(&bytes),
+            Some(Err(e)) => {
+                return Some((
+                    Err(InferenceError::Message(format!(
+                        "Chat completion stream failed: {}",
+                        e
+                    ))),
+                    state,
+                ))
+            }
+            None => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::llm::inference::{InferenceRequest, InferenceResponse};
+    use crate::llm::model::Model;
+    use crate::llm::openai::{ChatCompletionChunk, StreamOptions, StreamingChatCompletionRequest};
+    use crate::llm::prompt::{Instruction, Prompt};
+    use std::time::Instant;
+
+    #[test]
+    fn test_streaming_request_includes_usage() {
+        let prompt = Prompt {
+            history: vec![],
+            summary: None,
+            profile: None,
+            context: None,
+            question: Some("Which math course comes first?".to_string()),
+            instruction: Instruction::RAG,
+        };
+        let request = StreamingChatCompletionRequest {
+            request: InferenceRequest::new(prompt)
+                .into_chat_completion_request(&Model::Qwen)
+                .stream(true),
+            stream_options: StreamOptions {
+                include_usage: true,
+            },
+        };
+
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["stream_options"]["include_usage"], true);
+        assert!(body["messages"].is_array());
+
+        // the usage chunk sent last has no choices
+        let chunk: ChatCompletionChunk = serde_json::from_str(
+            r#"{"choices":[],"usage":{"prompt_tokens":70000,"completion_tokens":6,"total_tokens":70006}}"#,
+        )
+        .unwrap();
+        let response = InferenceResponse::from_chat_completion_chunk(chunk, &Instant::now());
+        assert_eq!(response.content, "");
+        assert_eq!(response.token_count, Some(6));
+        assert_eq!(response.prompt_token_count, Some(70000));
+        assert!(response.generation_time.is_some());
+    }
+}
@@ -5,6 +5,7 @@ You are talking with a student who has an academic profile. Every time the user
 If courses are listed as '0.00' for 'Earned' or 'Points', assume these classes are currently being completed this semester. \
 You should determine which year the student is currently enrolled as when you answer questions. \
 Use the following pieces of retrieved context to answer the question with accurate information about TCU courses, programs, and policies. \
+The context is split into numbered blocks. When you use information from a block, cite it inline with its number in square brackets, for example [1] or [2]. \
 Format your responses in a clear, concise manner using markdown for readability when appropriate. \
 When discussing courses, include course codes and relevant details such as prerequisites when available. \
 Review the chat history before answering to maintain conversation continuity.\
@@ -13,6 +14,9 @@ If information is available in the context, provide specific, actionable guidanc
 If information is partially available, clearly indicate what is known and what might require further clarification. \
 If you don't know or can't find the answer in the provided context, acknowledge this honestly and suggest how the user might find the information elsewhere (e.g., 'You may want to check with your academic advisor or the registrar's office for the most current information about this'). \
 Never mention that you are using retrieved context or vector search in your responses.";
+/// Context of RAG prompts when retrieval found nothing similar enough to the question.
+pub const NO_RELEVANT_CONTEXT: &str = "No relevant information about this question was found in the TCU documents. \
+Do not answer from memory with specific course codes, requirements or policies, tell the student that you could not find this information.";
 const TITLE_INSTRUCTION: &str = "Analyze the following conversation and generate a concise, descriptive title (maximum 8 words) that captures the main topic or purpose. The title should:
 1. Identify the core subject matter or question being discussed.
 2. Be specific enough to distinguish this conversation from others.
@@ -21,16 +25,51 @@ const TITLE_INSTRUCTION: &str = "Analyze the following conversation and generate
 5. Capture any unique aspects, project names, or specific requests.
 6. Be formatted in title case.
 Output only the title as plain text, without any formatting, quotation marks, or additional explanations.";
+const SUMMARY_INSTRUCTION: &str = "Summarize the conversation between a student and Echelon, TCU's academic advising assistant, so it can replace the messages in later prompts. \
+If a summary of the earlier conversation is given, extend it with the new messages instead of starting over. \
+Keep the student's goals, decisions, constraints and questions that are still open, as well as every course code, program, requirement and date that was mentioned. \
+Leave out greetings and repeated information. Write at most two short paragraphs of plain text without headings.";
+const REWRITE_INSTRUCTION: &str = "Rewrite the student's question as a standalone search query for TCU's catalog, program and policy documents. \
+Use the chat history to replace references like 'it', 'that class' or 'the prerequisites' with what they refer to, and keep course codes, programs and terms exactly as written. \
+Leave out topics of the chat history that the question does not ask about. \
+If the question asks about several unrelated things, write one query for each, at most three. \
+Output only the queries, one per line, without numbering, quotation marks or explanations.";
 
 #[derive(Debug)]
 pub enum Instruction {
     RAG,
     Title,
+    Summary,
+    Rewrite,
+}
+
+impl Instruction {
+    /// Identifies the instruction text and the prompt layout, stored with every answer so it can
+    /// be traced back to the prompt it was generated with. Bump it whenever either changes.
+    pub fn template_version(&self) -> &'static str {
+        match self {
+            Instruction::RAG => "rag-2",
+            Instruction::Title => "title-1",
+            Instruction::Summary => "summary-1",
+            Instruction::Rewrite => "rewrite-1",
+        }
+    }
+
+    pub fn text(&self) -> &'static str {
+        match self {
+            Instruction::RAG => RAG_INSTRUCTION,
+            Instruction::Title => TITLE_INSTRUCTION,
+            Instruction::Summary => SUMMARY_INSTRUCTION,
+            Instruction::Rewrite => REWRITE_INSTRUCTION,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Prompt {
     pub history: Vec<ApiMessage>,
+    // stands in for the messages before `history` once a conversation is too long to send whole
+    pub summary: Option<String>,
     pub profile: Option<String>,
     pub context: Option<String>,
     pub question: Option<String>,
@@ -47,6 +86,7 @@ impl Prompt {
     ) -> Self {
         Prompt {
             history,
+            summary: None,
             profile,
             instruction,
             context,
@@ -54,6 +94,11 @@ impl Prompt {
         }
     }
 
+    pub fn summary(mut self, summary: Option<String>) -> Self {
+        self.summary = summary;
+        self
+    }
+
     pub fn to_string_rag(self) -> String {
         let mut formatted_history = String::new();
         for message in &self.history {
@@ -63,17 +108,16 @@ impl Prompt {
             ));
         }
 
-        let profile = self.profile.unwrap_or_else(|| "".to_string());
-        let context = self.context.unwrap_or_else(|| "".to_string());
-        let question = self.question.unwrap_or_else(|| "".to_string());
+        let formatted_summary = format_summary(self.summary.as_deref());
+        let profile = self.profile.unwrap_or_default();
+        let context = self.context.unwrap_or_default();
+        let question = self.question.unwrap_or_default();
 
-        let instruction = match self.instruction {
-            Instruction::RAG => RAG_INSTRUCTION,
-            Instruction::Title => TITLE_INSTRUCTION,
-        };
+        let instruction = self.instruction.text();
 
         format!(
-            "Chat History:\n\
+            "{formatted_summary}\
+        Chat History:\n\
         {formatted_history}\
         Student's Academic Profile: \n\
         {}\n\
@@ -97,10 +141,7 @@ impl Prompt {
             ));
         }
 
-        let instruction = match self.instruction {
-            Instruction::RAG => RAG_INSTRUCTION,
-            Instruction::Title => TITLE_INSTRUCTION,
-        };
+        let instruction = self.instruction.text();
 
         format!(
             "Chat History:\n\
@@ -111,4 +152,53 @@ impl Prompt {
             instruction
         )
     }
+
+    pub fn to_string_summary(self) -> String {
+        let formatted_summary = format_summary(self.summary.as_deref());
+        let mut formatted_history = String::new();
+        for message in &self.history {
+            formatted_history.push_str(&format!(
+                "[role: {}, content: {}]\n",
+                message.role, message.content
+            ));
+        }
+
+        format!(
+            "{formatted_summary}\
+        New Messages:\n\
+        {formatted_history}\n\
+        Instruction:\n\
+        {}
+        ",
+            SUMMARY_INSTRUCTION
+        )
+    }
+
+    pub fn to_string_rewrite(self) -> String {
+        let mut formatted_history = String::new();
+        for message in &self.history {
+            formatted_history.push_str(&format!(
+                "[role: {}, content: {}]\n",
+                message.role, message.content
+            ));
+        }
+
+        format!(
+            "Chat History:\n\
+        {formatted_history}\n\
+        Question:\n\
+        {}\n\
+        Instruction:\n\
+        {}
+        ",
+            self.question.unwrap_or_default(),
+            REWRITE_INSTRUCTION
+        )
+    }
+}
+
+fn format_summary(summary: Option<&str>) -> String {
+    summary
+        .map(|summary| format!("Summary of the Earlier Conversation:\n{}\n", summary))
+        .unwrap_or_default()
 }
@@ -0,0 +1,105 @@
+use crate::llm::model::Model;
+
+/// Estimates how many tokens a text takes for a model, without loading its tokenizer.
+///
+/// ASCII text is counted with a rough characters per token ratio for the tokenizer of the model
+/// family, other characters count as one token each. The ratios are estimates for English text,
+/// not measurements, and unknown models use a low ratio, so their prompts are rather
+/// overestimated than cut off.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenCounter {
+    chars_per_token: f32,
+}
+
+impl TokenCounter {
+    pub fn new(chars_per_token: f32) -> Self {
+        Self { chars_per_token }
+    }
+
+    pub fn for_model(model: &Model) -> Self {
+        let chars_per_token = match model {
+            Model::GPT4o => 4.2,
+            Model::Llama3_3b | Model::Llama3 | Model::Phi4 | Model::Gemma3 | Model::CommandA => 4.0,
+            Model::Qwen | Model::BespokeMinicheck => 3.7,
+            Model::Mistral24b | Model::MistralSmall => 3.6,
+            Model::Custom(_) => 3.5,
+        };
+
+        Self::new(chars_per_token)
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        let (ascii, other) = text.chars().fold((0, 0), |(ascii, other), c| {
+            if c.is_ascii() {
+                (ascii + 1, other)
+            } else {
+                (ascii, other + 1)
+            }
+        });
+
+        (ascii as f32 / self.chars_per_token).ceil() as usize + other
+    }
+
+    /// The longest start of `text` that fits in `max_tokens`, cut after a whitespace if possible.
+    pub fn truncate<'a>(&self, text: &'a str, max_tokens: usize) -> &'a str {
+        if self.count(text) <= max_tokens {
+            return text;
+        }
+
+        let mut end = 0;
+        let mut tokens = 0.0;
+        for (idx, c) in text.char_indices() {
+            tokens += if c.is_ascii() {
+                1.0 / self.chars_per_token
+            } else {
+                1.0
+            };
+            if tokens.ceil() as usize > max_tokens {
+                break;
+            }
+            end = idx + c.len_utf8();
+        }
+
+        let cut = &text[..end];
+        if text[end..].starts_with(char::is_whitespace) {
+            return cut.trim_end();
+        }
+
+        match cut.rfind(char::is_whitespace) {
+            Some(whitespace) if whitespace > 0 => cut[..whitespace].trim_end(),
+            _ => cut,
+        }
+    }
+}
+
+impl Default for TokenCounter {
+    fn default() -> Self {
+        Self::for_model(&Model::Custom(String::new()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::llm::model::Model;
+    use crate::llm::tokens::TokenCounter;
+
+    #[test]
+    fn test_count_and_truncate() {
+        let counter = TokenCounter::for_model(&Model::Llama3);
+
+        assert_eq!(counter.count(""), 0);
+        assert_eq!(counter.count("What is COSC 30603?"), 5);
+        // every non-ASCII character is a token of its own
+        assert_eq!(counter.count("Añ"), 2);
+        assert!(
+            TokenCounter::for_model(&Model::Mistral24b).count("Data Structures")
+                > counter.count("Data Structures")
+        );
+
+        let profile = "Junior, Computer Science BS, minor in Mathematics";
+        assert_eq!(counter.truncate(profile, 100), profile);
+        let cut = counter.truncate(profile, 6);
+        assert_eq!(cut, "Junior, Computer Science");
+        assert!(counter.count(cut) <= 6);
+    }
+}
@@ -5,10 +5,11 @@ use axum::http::{
 use backend::api::router::create_router;
 use backend::app_state::AppState;
 use backend::config::{Config, Environment};
+use backend::jobs::{embeddings, retention};
 use backend::llm::inference;
-use backend::llm::model::Model;
 use backend::storage::postgres::RelationalStorage;
 use backend::storage::qdrant::QdrantAdapter;
+use backend::vectorization::embedding::embed;
 use std::sync::Arc;
 use tower_http::cors::CorsLayer;
 use tracing::{error, info};
@@ -26,6 +27,7 @@ async fn main() {
         .init();
 
     info!("Environment: {}", config.environment);
+    info!("LLM: {} ({})", config.llm_model, config.llm_backend);
 
     let origin = match config.environment {
         Environment::Development => "http://localhost:3000",
@@ -53,15 +55,28 @@ async fn main() {
         }
     };
 
-    let app = create_router(Arc::new(AppState {
+    let llm = inference::build(&config).unwrap_or_else(|e| {
+        error!("Unable to build LLM provider for backend '{}': {}", config.llm_backend, e);
+        panic!();
+    });
+
+    let app_state = Arc::new(AppState {
         relational_storage: RelationalStorage::new(&config.postgres_url)
             .await
             .expect("Unable to connect to Relational Storage (Postgres) from URL"),
-        vector_storage,
-        llm: inference::build(Model::Qwen, &config.ollama_url, config.ollama_port),
+        vector_storage: Box::new(vector_storage),
+        llm,
+        embedder: embed,
         config,
-    }))
-        .layer(cors);
+    });
+
+    retention::spawn(app_state.clone());
+
+    if app_state.config.semantic_search {
+        embeddings::spawn(app_state.clone());
+    }
+
+    let app = create_router(app_state).layer(cors);
 
 
     info!("Axum is up.");
@@ -0,0 +1,4 @@
+pub mod diversity;
+pub mod history;
+pub mod pipeline;
+pub mod source;
@@ -0,0 +1,226 @@
+use crate::rag::source::document_name;
+use crate::storage::vector::VectorDataPoint;
+use crate::vectorization::embedding::cosine_similarity;
+
+// points whose embeddings are at least this similar hold the same text
+const DUPLICATE_SIMILARITY: f32 = 0.98;
+// fewest words the end of one chunk and the start of another must share to be merged
+const MIN_OVERLAP_WORDS: usize = 5;
+
+/// Maximal marginal relevance selection, see `mmr`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MmrOptions {
+    // Weight of relevance against novelty, 1 keeps the retrieval order (Default: 0.7)
+    pub lambda: f32,
+    // Points fetched per vector search to select from (Default: 20)
+    pub candidates: u64,
+}
+
+impl Default for MmrOptions {
+    fn default() -> Self {
+        Self {
+            lambda: 0.7,
+            candidates: 20,
+        }
+    }
+}
+
+impl MmrOptions {
+    pub fn lambda(mut self, lambda: f32) -> Self {
+        self.lambda = lambda.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn candidates(mut self, candidates: u64) -> Self {
+        self.candidates = candidates;
+        self
+    }
+}
+
+/// Selects up to `count` candidates that are relevant to the query but unlike each other.
+///
+/// Relevance is the retrieval score scaled to 0..1 over the candidates, so similarity, fusion
+/// and reranker scores work alike. Novelty is measured with the cosine similarity of the stored
+/// embeddings, points without an embedding are never considered similar to another.
+pub fn mmr(candidates: Vec<VectorDataPoint>, lambda: f32, count: usize) -> Vec<VectorDataPoint> {
+    let relevance = normalized_scores(&candidates);
+    let mut remaining: Vec<(f32, VectorDataPoint)> =
+        relevance.into_iter().zip(candidates).collect();
+    let mut selected: Vec<VectorDataPoint> = vec![];
+
+    while selected.len() < count && !remaining.is_empty() {
+        let mut best = (0, f32::MIN);
+        for (idx, (relevance, point)) in remaining.iter().enumerate() {
+            let redundancy = selected
+                .iter()
+                .map(|other| similarity(point, other))
+                .fold(0.0, f32::max);
+            let value = lambda * relevance - (1.0 - lambda) * redundancy;
+
+            // ties keep the retrieval order
+            if value > best.1 {
+                best = (idx, value);
+            }
+        }
+
+        selected.push(remaining.remove(best.0).1);
+    }
+
+    selected
+}
+
+/// Drops points with the same text as a better ranked one and merges chunks of a document that
+/// overlap, like the neighbouring chunks of `chunk_by_words`, so the prompt never repeats text.
+/// A merged point keeps the place, id and score of the better ranked chunk.
+pub fn collapse(points: Vec<VectorDataPoint>) -> Vec<VectorDataPoint> {
+    let mut collapsed: Vec<VectorDataPoint> = vec![];
+
+    'points: for point in points {
+        for kept in collapsed.iter_mut() {
+            if is_duplicate(kept, &point) {
+                continue 'points;
+            }
+
+            if document_name(&kept.name) == document_name(&point.name) {
+                if let Some(content) = merge_overlap(&kept.content, &point.content)
+                    .or_else(|| merge_overlap(&point.content, &kept.content))
+                {
+                    kept.content = content;
+                    continue 'points;
+                }
+            }
+        }
+
+        collapsed.push(point);
+    }
+
+    collapsed
+}
+
+fn normalized_scores(points: &[VectorDataPoint]) -> Vec<f32> {
+    let scores: Vec<f32> = points
+        .iter()
+        .map(|point| point.score.unwrap_or_default())
+        .collect();
+    let min = scores.iter().copied().fold(f32::MAX, f32::min);
+    let max = scores.iter().copied().fold(f32::MIN, f32::max);
+
+    if max - min <= f32::EPSILON {
+        return vec![1.0; scores.len()];
+    }
+
+    scores
+        .iter()
+        .map(|score| (score - min) / (max - min))
+        .collect()
+}
+
+fn similarity(a: &VectorDataPoint, b: &VectorDataPoint) -> f32 {
+    match (&a.embedding, &b.embedding) {
+        (Some(a), Some(b)) => cosine_similarity(a, b),
+        _ => 0.0,
+    }
+}
+
+fn is_duplicate(a: &VectorDataPoint, b: &VectorDataPoint) -> bool {
+    a.content
+        .split_whitespace()
+        .eq(b.content.split_whitespace())
+        || similarity(a, b) >= DUPLICATE_SIMILARITY
+}
+
+// `first` followed by what `second` adds, if `second` starts with the last words of `first`
+fn merge_overlap(first: &str, second: &str) -> Option<String> {
+    let first: Vec<&str> = first.split_whitespace().collect();
+    let second: Vec<&str> = second.split_whitespace().collect();
+    let longest = first.len().min(second.len());
+
+    (MIN_OVERLAP_WORDS..=longest)
+        .rev()
+        .find(|&overlap| first[first.len() - overlap..] == second[..overlap])
+        .map(|overlap| [&first[..], &second[overlap..]].concat().join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rag::diversity::{collapse, mmr};
+    use crate::storage::vector::VectorDataPoint;
+
+    fn point(idx: usize, content: &str, embedding: Vec<f32>, score: f32) -> VectorDataPoint {
+        VectorDataPoint {
+            uuid: idx.to_string(),
+            name: format!("catalog.md_{}", idx),
+            content: content.to_string(),
+            embedding: Some(embedding),
+            sparse_embedding: None,
+            score: Some(score),
+        }
+    }
+
+    #[test]
+    fn test_mmr() {
+        let candidates = || {
+            vec![
+                point(0, "a", vec![1.0, 0.0], 0.9),
+                point(1, "b", vec![0.99, 0.1], 0.88),
+                point(2, "c", vec![0.0, 1.0], 0.7),
+            ]
+        };
+
+        // the near copy of the best point is passed over for the different one
+        let selected = mmr(candidates(), 0.5, 2);
+        let ids: Vec<&str> = selected.iter().map(|p| p.uuid.as_str()).collect();
+        assert_eq!(ids, ["0", "2"]);
+
+        // relevance alone keeps the retrieval order
+        let selected = mmr(candidates(), 1.0, 2);
+        let ids: Vec<&str> = selected.iter().map(|p| p.uuid.as_str()).collect();
+        assert_eq!(ids, ["0", "1"]);
+
+        assert_eq!(mmr(candidates(), 0.5, 5).len(), 3);
+    }
+
+    #[test]
+    fn test_collapse() {
+        let points = vec![
+            point(
+                1,
+                "five six seven eight nine ten eleven",
+                vec![1.0, 0.0],
+                0.9,
+            ),
+            point(3, "COSC  30603\nData Structures", vec![0.0, 1.0], 0.8),
+            // the previous chunk shares its last five words with the start of the first one
+            point(
+                0,
+                "one two three four five six seven eight nine",
+                vec![0.6, 0.8],
+                0.7,
+            ),
+            point(4, "COSC 30603 Data Structures", vec![0.8, 0.6], 0.6),
+        ];
+
+        let collapsed = collapse(points);
+        assert_eq!(collapsed.len(), 2);
+        assert_eq!(collapsed[0].uuid, "1");
+        assert_eq!(
+            collapsed[0].content,
+            "one two three four five six seven eight nine ten eleven"
+        );
+        assert_eq!(collapsed[1].uuid, "3");
+
+        // chunks of different documents are never merged
+        let mut other = point(5, "seven eight nine ten eleven twelve", vec![0.0, 1.0], 0.5);
+        other.name = "policies.md_0".to_string();
+        let collapsed = collapse(vec![
+            point(
+                1,
+                "four five six seven eight nine ten eleven",
+                vec![1.0, 0.0],
+                0.9,
+            ),
+            other,
+        ]);
+        assert_eq!(collapsed.len(), 2);
+    }
+}
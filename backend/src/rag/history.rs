@@ -0,0 +1,310 @@
+use crate::api::completions::ApiMessage;
+use crate::llm::budget::PromptBudget;
+use crate::llm::inference::{InferenceError, InferenceOptions, InferenceRequest, LlmProvider};
+use crate::llm::prompt::{Instruction, Prompt};
+use crate::llm::tokens::TokenCounter;
+use crate::storage::model::DBMessage;
+use crate::storage::postgres::RelationalStorage;
+use tracing::{info, warn};
+
+// tokens taken by the role and the brackets around every message in the prompt
+const MESSAGE_OVERHEAD: usize = 8;
+
+/// The history part of a prompt, recent messages verbatim and a summary of everything before.
+#[derive(Debug, Default)]
+pub struct RagHistory {
+    pub summary: Option<String>,
+    pub messages: Vec<ApiMessage>,
+}
+
+impl From<Vec<ApiMessage>> for RagHistory {
+    fn from(messages: Vec<ApiMessage>) -> Self {
+        Self {
+            summary: None,
+            messages,
+        }
+    }
+}
+
+impl RagHistory {
+    pub fn tokens(&self, counter: &TokenCounter) -> usize {
+        let summary = self
+            .summary
+            .as_deref()
+            .map_or(0, |summary| counter.count(summary));
+
+        summary
+            + self
+                .messages
+                .iter()
+                .map(|message| message_tokens(counter, &message.content))
+                .sum::<usize>()
+    }
+
+    /// Drops the oldest messages until the history fits its budget, the latest one is always kept.
+    pub fn truncate(mut self, budget: &PromptBudget) -> Self {
+        let counter = &budget.counter;
+        let mut tokens = self.tokens(counter);
+        let mut dropped = 0;
+
+        while tokens > budget.history && dropped + 1 < self.messages.len() {
+            tokens -= message_tokens(counter, &self.messages[dropped].content);
+            dropped += 1;
+        }
+
+        if dropped > 0 {
+            warn!(
+                "Dropped the {} oldest history messages to fit {} tokens",
+                dropped, budget.history
+            );
+            self.messages.drain(..dropped);
+        }
+
+        self
+    }
+}
+
+/// Fits the branch of a stored conversation into the history budget of a prompt.
+///
+/// Older messages are replaced by an LLM summary. Summaries are stored per branch position and
+/// extended with the messages that fell out of the window since, instead of being regenerated.
+pub struct HistoryManager<'a> {
+    storage: &'a RelationalStorage,
+    llm: &'a dyn LlmProvider,
+    budget: PromptBudget,
+}
+
+impl<'a> HistoryManager<'a> {
+    pub fn new(
+        storage: &'a RelationalStorage,
+        llm: &'a dyn LlmProvider,
+        budget: PromptBudget,
+    ) -> Self {
+        Self {
+            storage,
+            llm,
+            budget,
+        }
+    }
+
+    /// `branch` is ordered oldest first and ends with the question being answered.
+    pub async fn fit(&self, branch: Vec<DBMessage>) -> Result<RagHistory, sqlx::Error> {
+        let budget = self.budget.history;
+        let counter = &self.budget.counter;
+        let tokens: Vec<usize> = branch
+            .iter()
+            .map(|message| message_tokens(counter, &message.content))
+            .collect();
+
+        if tokens.iter().sum::<usize>() <= budget {
+            return Ok(history(None, branch, 0));
+        }
+
+        let ids: Vec<i32> = branch.iter().map(|message| message.id).collect();
+        // number of messages the cached summary stands for and its text
+        let cached = self
+            .storage
+            .get_latest_summary(&ids)
+            .await?
+            .and_then(|summary| {
+                ids.iter()
+                    .position(|id| *id == summary.through_message_id)
+                    .map(|idx| (idx + 1, summary.content))
+            });
+
+        if let Some((covered, summary)) = &cached {
+            if counter.count(summary) + tokens[*covered..].iter().sum::<usize>() <= budget {
+                let covered = *covered;
+                return Ok(history(cached.map(|(_, summary)| summary), branch, covered));
+            }
+        }
+
+        // the newest messages are kept in half the budget, so the summary lasts for a few turns
+        let mut keep_from = branch.len() - 1;
+        let mut kept = tokens[keep_from];
+        while keep_from > 0 && kept + tokens[keep_from - 1] <= budget / 2 {
+            keep_from -= 1;
+            kept += tokens[keep_from];
+        }
+
+        let (mut summary, mut start) = match cached {
+            Some((covered, summary)) if covered <= keep_from => (Some(summary), covered),
+            _ => (None, 0),
+        };
+
+        // summarized in pieces that fit the budget, each extending the summary of the previous
+        while start < keep_from {
+            let mut end = start + 1;
+            let mut chunk_tokens = tokens[start];
+            while end < keep_from && chunk_tokens + tokens[end] <= budget {
+                chunk_tokens += tokens[end];
+                end += 1;
+            }
+
+            let content = match self.summarize(summary.clone(), &branch[start..end]).await {
+                Ok(content) => content,
+                Err(e) => {
+                    warn!(
+                        "Failed to summarize conversation {}: {}",
+                        branch[0].conversation_id, e
+                    );
+                    return Ok(history(summary, branch, start).truncate(&self.budget));
+                }
+            };
+
+            self.storage
+                .create_summary(branch[0].conversation_id, branch[end - 1].id, &content)
+                .await?;
+            summary = Some(content);
+            start = end;
+        }
+
+        info!(
+            "Summarized {} messages of conversation {}",
+            keep_from, branch[0].conversation_id
+        );
+
+        Ok(history(summary, branch, keep_from).truncate(&self.budget))
+    }
+
+    async fn summarize(
+        &self,
+        previous: Option<String>,
+        messages: &[DBMessage],
+    ) -> Result<String, InferenceError> {
+        let history = messages
+            .iter()
+            .map(|message| ApiMessage {
+                role: message.role.clone(),
+                content: message.content.clone(),
+            })
+            .collect();
+
+        let prompt = Prompt::new(history, None, None, None, Instruction::Summary).summary(previous);
+        // leaves at least half of the history budget to the recent messages
+        let options = InferenceOptions::default()
+            .context_window(self.budget.context_window as u64)
+            .max_tokens((self.budget.history / 4) as i32);
+
+        let response = self
+            .llm
+            .generate(InferenceRequest::new(prompt).options(options))
+            .await?;
+
+        Ok(response.content.trim().to_string())
+    }
+}
+
+fn message_tokens(counter: &TokenCounter, content: &str) -> usize {
+    counter.count(content) + MESSAGE_OVERHEAD
+}
+
+fn history(summary: Option<String>, branch: Vec<DBMessage>, skip: usize) -> RagHistory {
+    RagHistory {
+        summary,
+        messages: branch
+            .into_iter()
+            .skip(skip)
+            .map(ApiMessage::from)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::completions::ApiMessage;
+    use crate::api::testing::{test_app, test_user};
+    use crate::llm::budget::PromptBudget;
+    use crate::llm::tokens::TokenCounter;
+    use crate::rag::history::{HistoryManager, RagHistory};
+    use crate::storage::model::DBMessageRole;
+    use sqlx::PgPool;
+
+    fn history_budget(history: usize) -> PromptBudget {
+        PromptBudget {
+            history,
+            counter: TokenCounter::new(4.0),
+            ..PromptBudget::default()
+        }
+    }
+
+    #[test]
+    fn test_truncate() {
+        let messages = ["first", "second", "third"]
+            .into_iter()
+            .map(|content| ApiMessage {
+                role: DBMessageRole::User,
+                content: content.repeat(8),
+            })
+            .collect::<Vec<_>>();
+
+        let history = RagHistory::from(messages).truncate(&history_budget(40));
+        assert_eq!(history.messages.len(), 2);
+        assert!(history.messages[0].content.starts_with("second"));
+
+        // the question is kept even if it does not fit on its own
+        let history = history.truncate(&history_budget(1));
+        assert_eq!(history.messages.len(), 1);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_rolling_summary(pool: PgPool) {
+        let (_, state) = test_app(pool).await;
+        let (user, _) = test_user(&state, "alice@tcu.edu").await;
+        let storage = &state.relational_storage;
+        let budget = history_budget(330);
+        let manager = HistoryManager::new(storage, state.llm.as_ref(), budget.clone());
+
+        let conversation = storage
+            .create_conversation(user.id, "Degree plan".to_string())
+            .await
+            .unwrap();
+        // 58 tokens per message including the overhead
+        let turn = |n: usize| {
+            let role = if n.is_multiple_of(2) {
+                DBMessageRole::User
+            } else {
+                DBMessageRole::Assistant
+            };
+            storage.create_message(conversation.id, format!("{:0>200}", n), role)
+        };
+
+        for n in 0..4 {
+            turn(n).await.unwrap();
+        }
+        let branch = storage.get_active_branch(conversation.id).await.unwrap();
+        let history = manager.fit(branch).await.unwrap();
+        assert!(history.summary.is_none());
+        assert_eq!(history.messages.len(), 4);
+
+        for n in 4..7 {
+            turn(n).await.unwrap();
+        }
+        let branch = storage.get_active_branch(conversation.id).await.unwrap();
+        let history = manager.fit(branch).await.unwrap();
+        // two messages fit in half the budget, the five before them are summarized
+        let summary = history.summary.unwrap();
+        assert_eq!(summary, "Summary of 5 messages");
+        assert_eq!(history.messages.len(), 2);
+
+        // the cached summary is reused while the newer messages fit next to it
+        turn(7).await.unwrap();
+        let branch = storage.get_active_branch(conversation.id).await.unwrap();
+        let history = manager.fit(branch).await.unwrap();
+        assert_eq!(history.summary.as_deref(), Some(summary.as_str()));
+        assert_eq!(history.messages.len(), 3);
+
+        // and extended instead of regenerated once they do not
+        for n in 8..11 {
+            turn(n).await.unwrap();
+        }
+        let branch = storage.get_active_branch(conversation.id).await.unwrap();
+        let history = manager.fit(branch).await.unwrap();
+        assert_eq!(
+            history.summary.as_deref(),
+            Some("Summary of 5 messages / Summary of 4 messages")
+        );
+        assert_eq!(history.messages.len(), 2);
+        assert!(history.tokens(&budget.counter) <= budget.history);
+    }
+}
@@ -0,0 +1,1009 @@
+use crate::api::completions::ApiMessage;
+use crate::llm::budget::PromptBudget;
+use crate::llm::inference::{
+    InferenceError, InferenceOptions, InferenceRequest, InferenceResponse, InferenceStream,
+    LlmProvider,
+};
+use crate::llm::prompt::{Instruction, Prompt, NO_RELEVANT_CONTEXT};
+use crate::rag::diversity::{collapse, mmr, MmrOptions};
+use crate::rag::history::RagHistory;
+use crate::rag::source::Source;
+use crate::storage::model::DBMessageRole;
+use crate::storage::vector::{
+    SearchMode, VectorDataPoint, VectorQuery, VectorQueryOptions, VectorStorage,
+    VectorStorageError,
+};
+use crate::vectorization::embedding::{embed, EmbeddingError};
+use crate::vectorization::rerank::{rerank, RerankOptions};
+use crate::vectorization::sparse::sparse_embed_query;
+use fastembed::Embedding;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+pub type Embedder = fn(String) -> Result<Embedding, EmbeddingError>;
+/// Scores documents against a query, in the order of the documents.
+pub type Reranker = fn(&str, &[String]) -> Result<Vec<f32>, EmbeddingError>;
+
+// messages before the question the rewrite of a query can refer to
+const REWRITE_HISTORY: usize = 6;
+// queries a question can be split into by the rewrite
+const MAX_SEARCH_QUERIES: usize = 3;
+// enough for three short queries, a rewrite that goes on longer is not a list of queries
+const REWRITE_MAX_TOKENS: i32 = 96;
+// the rewrite holds up every answer, the question is searched as it is after this long
+const REWRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The retrieval-augmented generation flow behind every completion, independent of Axum.
+///
+/// Each stage is exposed on its own so callers can inspect or replace intermediate results:
+/// `build_query` or `rewrite_query` -> `retrieve` (-> `rerank_points` -> `mmr`) -> `collapse` ->
+/// `assemble_context` -> `build_prompt` -> `generate`.
+pub struct RagPipeline<'a> {
+    vector_storage: &'a dyn VectorStorage,
+    llm: &'a dyn LlmProvider,
+    collection: String,
+    embedder: Embedder,
+    reranker: Reranker,
+    query_options: VectorQueryOptions,
+    rerank: Option<RerankOptions>,
+    mmr: Option<MmrOptions>,
+    rewrite: bool,
+    rewrite_timeout: Duration,
+    options: InferenceOptions,
+    budget: PromptBudget,
+}
+
+/// Milliseconds spent in the retrieval stages, summed over all searches. The optional stages are
+/// only timed when they are enabled.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct RagTimings {
+    pub rewrite_ms: Option<u64>,
+    pub embedding_ms: u64,
+    pub search_ms: u64,
+    pub rerank_ms: Option<u64>,
+    pub mmr_ms: Option<u64>,
+}
+
+/// The points found for a query, the most relevant first.
+#[derive(Debug)]
+pub struct RagRetrieval {
+    pub points: Vec<VectorDataPoint>,
+    pub timings: RagTimings,
+}
+
+/// A prompt together with the sources its context was assembled from.
+#[derive(Debug)]
+pub struct RagPrompt {
+    pub prompt: Prompt,
+    pub sources: Vec<Source>,
+    pub search_queries: Vec<String>,
+    pub timings: RagTimings,
+}
+
+#[derive(Debug)]
+pub struct RagCompletion {
+    pub response: InferenceResponse,
+    pub sources: Vec<Source>,
+    pub search_queries: Vec<String>,
+    pub timings: RagTimings,
+}
+
+pub struct RagStream {
+    pub stream: InferenceStream,
+    pub sources: Vec<Source>,
+    pub search_queries: Vec<String>,
+    pub timings: RagTimings,
+}
+
+#[derive(Debug)]
+pub struct RagQuery {
+    // the latest user message, answered by the LLM
+    pub question: String,
+    // texts that are embedded for the vector search, their results are merged
+    pub search_queries: Vec<String>,
+}
+
+impl<'a> RagPipeline<'a> {
+    pub fn new(
+        vector_storage: &'a dyn VectorStorage,
+        llm: &'a dyn LlmProvider,
+        collection: &str,
+    ) -> Self {
+        Self {
+            vector_storage,
+            llm,
+            collection: collection.to_string(),
+            embedder: embed,
+            reranker: rerank,
+            query_options: VectorQueryOptions::default(),
+            rerank: None,
+            mmr: None,
+            rewrite: false,
+            rewrite_timeout: REWRITE_TIMEOUT,
+            options: InferenceOptions::default(),
+            budget: PromptBudget::default(),
+        }
+    }
+
+    /// Replace the embedding function, e.g. with a fixed vector in tests.
+    pub fn embedder(mut self, embedder: Embedder) -> Self {
+        self.embedder = embedder;
+        self
+    }
+
+    /// Replace the cross-encoder, e.g. with a scoring function in tests.
+    pub fn reranker(mut self, reranker: Reranker) -> Self {
+        self.reranker = reranker;
+        self
+    }
+
+    /// How many points each vector search returns and how similar they must be to the query.
+    pub fn query_options(mut self, options: VectorQueryOptions) -> Self {
+        self.query_options = options;
+        self
+    }
+
+    /// Over-fetches candidates and reranks them against the question, `None` keeps the order of
+    /// the vector search.
+    pub fn rerank(mut self, options: Option<RerankOptions>) -> Self {
+        self.rerank = options;
+        self
+    }
+
+    /// Over-fetches candidates and selects the points by maximal marginal relevance, so similar
+    /// chunks do not crowd out the rest. `None` keeps the retrieved points as they are.
+    pub fn mmr(mut self, options: Option<MmrOptions>) -> Self {
+        self.mmr = options;
+        self
+    }
+
+    /// Lets the LLM turn the question into standalone search queries before retrieval.
+    pub fn rewrite(mut self, rewrite: bool) -> Self {
+        self.rewrite = rewrite;
+        self
+    }
+
+    /// How long the rewrite may take before the question is searched as it is.
+    pub fn rewrite_timeout(mut self, timeout: Duration) -> Self {
+        self.rewrite_timeout = timeout;
+        self
+    }
+
+    pub fn options(mut self, options: InferenceOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets the context window of the model and how it is split between the parts of the prompt,
+    /// answers are limited to the completion allowance of the budget.
+    pub fn budget(mut self, budget: PromptBudget) -> Self {
+        self.options.context_window = Some(budget.context_window as u64);
+        self.options.max_tokens = Some(budget.completion as i32);
+        self.budget = budget;
+        self
+    }
+
+    /// Searches with the question and the user message before it, so short follow-ups keep the
+    /// subject of the previous question without drifting to older topics.
+    pub fn build_query(&self, messages: &[ApiMessage]) -> Result<RagQuery, RagError> {
+        let user_queries: Vec<&str> = messages
+            .iter()
+            .filter(|message| matches!(message.role, DBMessageRole::User))
+            .map(|message| message.content.as_str())
+            .collect();
+
+        let question = user_queries.last().ok_or(RagError::MissingQuestion)?;
+        let recent = &user_queries[user_queries.len().saturating_sub(2)..];
+
+        Ok(RagQuery {
+            question: question.to_string(),
+            search_queries: vec![recent.join(" ")],
+        })
+    }
+
+    /// Asks the LLM for standalone search queries that resolve references to the recent history,
+    /// one per topic of the question. Falls back to `build_query` if the rewrite fails or takes
+    /// longer than the rewrite timeout, and skips the LLM for the first question of a conversation.
+    pub async fn rewrite_query(&self, messages: &[ApiMessage]) -> Result<RagQuery, RagError> {
+        let query = self.build_query(messages)?;
+        let Some(asked) = messages
+            .iter()
+            .rposition(|message| matches!(message.role, DBMessageRole::User))
+        else {
+            return Ok(query);
+        };
+        if asked == 0 {
+            return Ok(query);
+        }
+
+        let history = messages[asked.saturating_sub(REWRITE_HISTORY)..asked].to_vec();
+        let prompt = Prompt::new(
+            history,
+            None,
+            None,
+            Some(query.question.clone()),
+            Instruction::Rewrite,
+        );
+        let request = InferenceRequest::new(prompt).options(
+            InferenceOptions::default()
+                .context_window(self.budget.context_window as u64)
+                .max_tokens(REWRITE_MAX_TOKENS),
+        );
+
+        let rewritten =
+            match tokio::time::timeout(self.rewrite_timeout, self.llm.generate(request)).await {
+                Ok(Ok(response)) => parse_search_queries(&response.content),
+                Ok(Err(e)) => {
+                    warn!("Failed to rewrite the search query: {}", e);
+                    vec![]
+                }
+                Err(_) => {
+                    warn!(
+                        "Rewriting the search query took longer than {:?}",
+                        self.rewrite_timeout
+                    );
+                    vec![]
+                }
+            };
+
+        if rewritten.is_empty() {
+            return Ok(query);
+        }
+
+        debug!("Rewrote '{}' as {:?}", query.question, rewritten);
+
+        Ok(RagQuery {
+            question: query.question,
+            search_queries: rewritten,
+        })
+    }
+
+    /// Searches the collection with every search query and, when available, the academic profile.
+    /// The results of the queries are interleaved by rank so each query contributes its best
+    /// points first, the profile results follow. Points found more than once are only returned
+    /// once. With reranking or MMR enabled every search over-fetches candidates, reranking orders
+    /// them by relevance to the question and MMR picks a varied selection of the best ones. As many
+    /// points as a single search returns are kept, or `top_n` when reranking.
+    pub async fn retrieve(
+        &self,
+        query: &RagQuery,
+        profile: Option<&str>,
+    ) -> Result<RagRetrieval, RagError> {
+        let mut timings = RagTimings::default();
+        let mut results = vec![];
+        for search_query in &query.search_queries {
+            results.push(self.search(search_query.clone(), &mut timings).await?);
+        }
+        let mut points = interleave(results);
+
+        if let Some(profile) = profile.filter(|profile| !profile.is_empty()) {
+            for point in self.search(profile.to_string(), &mut timings).await? {
+                if !points.iter().any(|existing| existing.uuid == point.uuid) {
+                    points.push(point);
+                }
+            }
+        }
+
+        if self.rerank.is_some() {
+            let started = Instant::now();
+            if let Err(e) = self.rerank_points(&query.question, &mut points).await {
+                // the order of the vector search is still better than no answer
+                warn!("Failed to rerank {} chunks: {}", points.len(), e);
+            }
+            timings.rerank_ms = Some(elapsed_ms(started));
+        }
+
+        let keep = self.rerank.as_ref().map(|options| options.top_n);
+        if let Some(options) = &self.mmr {
+            let started = Instant::now();
+            let count = keep.unwrap_or(self.query_options.limit as usize);
+            points = mmr(points, options.lambda, count);
+            timings.mmr_ms = Some(elapsed_ms(started));
+        } else if let Some(keep) = keep {
+            points.truncate(keep);
+        }
+
+        debug!(
+            "Retrieved {} chunks of {} in {:?}",
+            points.len(),
+            self.collection,
+            timings
+        );
+
+        Ok(RagRetrieval { points, timings })
+    }
+
+    async fn search(
+        &self,
+        text: String,
+        timings: &mut RagTimings,
+    ) -> Result<Vec<VectorDataPoint>, RagError> {
+        let started = Instant::now();
+        let sparse = match self.query_options.mode {
+            SearchMode::Dense => None,
+            SearchMode::Hybrid => Some(sparse_embed_query(&text)),
+        };
+        let embedder = self.embedder;
+        // the embedding model runs on the CPU, once for every search query and the profile
+        let dense = tokio::task::spawn_blocking(move || embedder(text))
+            .await
+            .map_err(|e| EmbeddingError::Message(format!("Embedding task failed: {}", e)))??;
+        let query = VectorQuery { dense, sparse };
+        timings.embedding_ms += elapsed_ms(started);
+
+        let candidates = [
+            self.rerank.as_ref().map(|options| options.candidates),
+            self.mmr.as_ref().map(|options| options.candidates),
+        ];
+        let limit = candidates
+            .into_iter()
+            .flatten()
+            .fold(self.query_options.limit, u64::max);
+        let options = self.query_options.clone().limit(limit);
+
+        let started = Instant::now();
+        let response = self
+            .vector_storage
+            .query(&self.collection, query, &options)
+            .await?;
+        timings.search_ms += elapsed_ms(started);
+
+        Ok(response.points)
+    }
+
+    /// Scores the points against the question with the reranker and orders them by it, their
+    /// score is replaced by the reranker score. The points are left as they are on errors.
+    pub async fn rerank_points(
+        &self,
+        question: &str,
+        points: &mut [VectorDataPoint],
+    ) -> Result<(), RagError> {
+        let reranker = self.reranker;
+        let question = question.to_string();
+        let documents: Vec<String> = points.iter().map(|point| point.content.clone()).collect();
+        // the cross-encoder runs for hundreds of milliseconds on the CPU
+        let scores = tokio::task::spawn_blocking(move || reranker(&question, &documents))
+            .await
+            .map_err(|e| EmbeddingError::Message(format!("Rerank task failed: {}", e)))??;
+
+        if scores.len() != points.len() {
+            return Err(EmbeddingError::Message(format!(
+                "Reranker returned {} scores for {} chunks",
+                scores.len(),
+                points.len()
+            ))
+            .into());
+        }
+
+        for (point, score) in points.iter_mut().zip(scores) {
+            point.score = Some(score);
+        }
+        // stable, so equally relevant points keep the order of the vector search
+        points.sort_by(|a, b| b.score.unwrap().total_cmp(&a.score.unwrap()));
+
+        Ok(())
+    }
+
+    /// Numbers every point as a context block so the model can cite it as `[n]`. Points are taken
+    /// in order while they fit in `max_tokens`, the first one is cut to fit if necessary.
+    /// Without points the context tells the model that nothing relevant was found.
+    pub fn assemble_context(
+        &self,
+        points: &[VectorDataPoint],
+        max_tokens: usize,
+    ) -> (String, Vec<Source>) {
+        if points.is_empty() {
+            info!(
+                "No chunks of {} matched the question closely enough",
+                self.collection
+            );
+            return (NO_RELEVANT_CONTEXT.to_string(), vec![]);
+        }
+
+        let counter = &self.budget.counter;
+        let mut context = String::new();
+        let mut sources = vec![];
+        let mut tokens = 0;
+
+        for point in points {
+            let source = Source::from_point(sources.len() + 1, point);
+            let mut block = format!(
+                "[{}] {}\n{}\n\n",
+                source.index,
+                source.document,
+                point.content.trim()
+            );
+            let block_tokens = counter.count(&block);
+
+            if tokens + block_tokens > max_tokens {
+                if !sources.is_empty() {
+                    break;
+                }
+                warn!(
+                    "Cut retrieved chunk {} from {} to {} tokens",
+                    point.uuid, block_tokens, max_tokens
+                );
+                block = format!("{}\n\n", counter.truncate(&block, max_tokens.saturating_sub(1)));
+            }
+
+            tokens += counter.count(&block);
+            context.push_str(&block);
+            sources.push(source);
+        }
+
+        if sources.len() < points.len() {
+            warn!(
+                "Dropped {} of {} retrieved chunks to fit {} tokens",
+                points.len() - sources.len(),
+                points.len(),
+                max_tokens
+            );
+        }
+
+        (context, sources)
+    }
+
+    /// Cuts the academic profile to its share of the budget.
+    pub fn fit_profile(&self, profile: Option<String>) -> Option<String> {
+        let profile = profile?;
+        let counter = &self.budget.counter;
+        let tokens = counter.count(&profile);
+
+        if tokens <= self.budget.profile {
+            return Some(profile);
+        }
+
+        warn!(
+            "Cut academic profile from {} to {} tokens",
+            tokens, self.budget.profile
+        );
+
+        Some(counter.truncate(&profile, self.budget.profile).to_string())
+    }
+
+    /// Cuts the question to what the instruction leaves of its budget. The full question is still
+    /// part of the history, this only bounds the copy below the context.
+    pub fn fit_question(&self, mut query: RagQuery) -> RagQuery {
+        let counter = &self.budget.counter;
+        let budget = self
+            .budget
+            .instruction
+            .saturating_sub(counter.count(Instruction::RAG.text()));
+        let tokens = counter.count(&query.question);
+
+        if tokens > budget {
+            warn!("Cut question from {} to {} tokens", tokens, budget);
+            query.question = counter.truncate(&query.question, budget).to_string();
+        }
+
+        query
+    }
+
+    pub fn build_prompt(
+        &self,
+        history: RagHistory,
+        profile: Option<String>,
+        context: String,
+        query: RagQuery,
+    ) -> Prompt {
+        Prompt::new(
+            history.messages,
+            profile,
+            Some(context),
+            Some(query.question),
+            Instruction::RAG,
+        )
+        .summary(history.summary)
+    }
+
+    /// Runs every stage up to and including prompt assembly.
+    ///
+    /// The question is rewritten into search queries when enabled. Every part is trimmed to its
+    /// share of the budget, the question shares the instruction budget. Histories that were not
+    /// fitted by a `HistoryManager` lose their oldest messages, and whatever the profile and the
+    /// history leave unused goes to the retrieved context, after duplicate and overlapping chunks
+    /// were collapsed. A latest message longer than the history budget shrinks the context.
+    pub async fn prepare(
+        &self,
+        history: impl Into<RagHistory>,
+        profile: Option<String>,
+    ) -> Result<RagPrompt, RagError> {
+        let budget = &self.budget;
+        let history = history.into().truncate(budget);
+        let profile = self.fit_profile(profile);
+
+        let started = Instant::now();
+        let query = if self.rewrite {
+            self.rewrite_query(&history.messages).await?
+        } else {
+            self.build_query(&history.messages)?
+        };
+        let rewrite_ms = self.rewrite.then(|| elapsed_ms(started));
+        let query = self.fit_question(query);
+        let search_queries = query.search_queries.clone();
+
+        let retrieval = self.retrieve(&query, profile.as_deref()).await?;
+        let retrieved = retrieval.points.len();
+        let points = collapse(retrieval.points);
+        if points.len() < retrieved {
+            debug!(
+                "Collapsed {} retrieved chunks into {}",
+                retrieved,
+                points.len()
+            );
+        }
+
+        let history_tokens = history.tokens(&budget.counter);
+        let profile_tokens = profile
+            .as_deref()
+            .map_or(0, |profile| budget.counter.count(profile));
+        // the latest message is kept even if it alone overflows the history, the overflow is
+        // taken from the context
+        let context_budget = (budget.context + budget.history)
+            .saturating_sub(history_tokens)
+            + budget.profile.saturating_sub(profile_tokens);
+        let (context, sources) = self.assemble_context(&points, context_budget);
+
+        debug!(
+            "Prompt tokens: profile {}/{}, history {}/{}, context {}/{}, completion {}",
+            profile_tokens,
+            budget.profile,
+            history_tokens,
+            budget.history,
+            budget.counter.count(&context),
+            context_budget,
+            budget.completion
+        );
+
+        Ok(RagPrompt {
+            prompt: self.build_prompt(history, profile, context, query),
+            sources,
+            search_queries,
+            timings: RagTimings {
+                rewrite_ms,
+                ..retrieval.timings
+            },
+        })
+    }
+
+    pub async fn generate(&self, prompt: Prompt) -> Result<InferenceResponse, RagError> {
+        let request = InferenceRequest::new(prompt).options(self.options.clone());
+
+        Ok(self.llm.generate(request).await?)
+    }
+
+    pub async fn generate_stream(&self, prompt: Prompt) -> Result<InferenceStream, RagError> {
+        let request = InferenceRequest::new(prompt).options(self.options.clone());
+
+        Ok(self.llm.generate_stream(request).await?)
+    }
+
+    pub async fn complete(
+        &self,
+        history: impl Into<RagHistory>,
+        profile: Option<String>,
+    ) -> Result<RagCompletion, RagError> {
+        let prepared = self.prepare(history, profile).await?;
+
+        Ok(RagCompletion {
+            response: self.generate(prepared.prompt).await?,
+            sources: prepared.sources,
+            search_queries: prepared.search_queries,
+            timings: prepared.timings,
+        })
+    }
+
+    pub async fn complete_stream(
+        &self,
+        history: impl Into<RagHistory>,
+        profile: Option<String>,
+    ) -> Result<RagStream, RagError> {
+        let prepared = self.prepare(history, profile).await?;
+
+        Ok(RagStream {
+            stream: self.generate_stream(prepared.prompt).await?,
+            sources: prepared.sources,
+            search_queries: prepared.search_queries,
+            timings: prepared.timings,
+        })
+    }
+}
+
+// one query per line, list markers and quotes the model may add are removed
+fn parse_search_queries(content: &str) -> Vec<String> {
+    let mut queries: Vec<String> = vec![];
+
+    for line in content.lines() {
+        let query = line
+            .trim()
+            .trim_start_matches(|c: char| c.is_ascii_digit() || matches!(c, '.' | ')' | '-' | '*'))
+            .trim()
+            .trim_matches(['"', '\'', '`'])
+            .trim();
+
+        if !query.is_empty() && !queries.iter().any(|existing| existing == query) {
+            queries.push(query.to_string());
+        }
+    }
+
+    queries.truncate(MAX_SEARCH_QUERIES);
+    queries
+}
+
+// first points of every result list, then the second ones and so on, without duplicates
+fn interleave(results: Vec<Vec<VectorDataPoint>>) -> Vec<VectorDataPoint> {
+    let mut results: Vec<_> = results.into_iter().map(Vec::into_iter).collect();
+    let mut points: Vec<VectorDataPoint> = vec![];
+
+    loop {
+        let mut exhausted = true;
+        for result in results.iter_mut() {
+            if let Some(point) = result.next() {
+                exhausted = false;
+                if !points.iter().any(|existing| existing.uuid == point.uuid) {
+                    points.push(point);
+                }
+            }
+        }
+
+        if exhausted {
+            return points;
+        }
+    }
+}
+
+fn elapsed_ms(started: Instant) -> u64 {
+    started.elapsed().as_millis() as u64
+}
+
+#[derive(Debug, Error)]
+pub enum RagError {
+    #[error("RagError occurred: no user message to answer")]
+    MissingQuestion,
+    #[error(transparent)]
+    Embedding(#[from] EmbeddingError),
+    #[error(transparent)]
+    VectorStorage(#[from] VectorStorageError),
+    #[error(transparent)]
+    Inference(#[from] InferenceError),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::completions::ApiMessage;
+    use crate::llm::budget::PromptBudget;
+    use crate::llm::mock::MockAdapter;
+    use crate::llm::model::Model;
+    use crate::llm::prompt::{Instruction, NO_RELEVANT_CONTEXT};
+    use crate::llm::tokens::TokenCounter;
+    use crate::rag::diversity::MmrOptions;
+    use crate::rag::pipeline::{RagError, RagPipeline};
+    use crate::storage::model::DBMessageRole;
+    use crate::storage::vector::{
+        SearchMode, VectorDataPoint, VectorQuery, VectorQueryOptions, VectorStorage,
+        VectorStorageError, VectorStorageQueryResponse, VectorStorageResponse,
+    };
+    use crate::vectorization::embedding::EmbeddingError;
+    use crate::vectorization::rerank::RerankOptions;
+    use async_trait::async_trait;
+    use fastembed::{Embedding, EmbeddingModel};
+    use std::time::Duration;
+
+    struct StaticVectorStorage;
+
+    #[async_trait]
+    impl VectorStorage for StaticVectorStorage {
+        async fn create_collection(
+            &self,
+            _: &str,
+            _: EmbeddingModel,
+            _: SearchMode,
+        ) -> Result<VectorStorageResponse, VectorStorageError> {
+            Ok(VectorStorageResponse { time: 0.0 })
+        }
+
+        async fn query(
+            &self,
+            collection_name: &str,
+            query: VectorQuery,
+            options: &VectorQueryOptions,
+        ) -> Result<VectorStorageQueryResponse, VectorStorageError> {
+            if options.mode == SearchMode::Hybrid && query.sparse.is_none() {
+                return Err(VectorStorageError::Message(
+                    "missing sparse vector".to_string(),
+                ));
+            }
+
+            // scores from 1.0 down to 0.4 in steps of 0.1
+            let points = (0..7)
+                .map(|idx| VectorDataPoint {
+                    uuid: idx.to_string(),
+                    name: format!("{}_{}", collection_name, idx),
+                    content: format!("[chunk {}]", idx),
+                    embedding: None,
+                    sparse_embedding: None,
+                    score: Some(1.0 - idx as f32 / 10.0),
+                })
+                .filter(|point| {
+                    options
+                        .score_threshold
+                        .is_none_or(|threshold| point.score.unwrap() >= threshold)
+                })
+                .skip(options.offset as usize)
+                .take(options.limit as usize)
+                .collect();
+
+            Ok(VectorStorageQueryResponse { time: 0.0, points })
+        }
+
+        async fn add_vectors(
+            &self,
+            _: &str,
+            _: Vec<VectorDataPoint>,
+        ) -> Result<VectorStorageResponse, VectorStorageError> {
+            Ok(VectorStorageResponse { time: 0.0 })
+        }
+    }
+
+    fn fixed_embedding(_: String) -> Result<Embedding, EmbeddingError> {
+        Ok(vec![0.0; 4])
+    }
+
+    // ranks the points of StaticVectorStorage in reverse
+    fn reverse_reranker(_: &str, documents: &[String]) -> Result<Vec<f32>, EmbeddingError> {
+        Ok((0..documents.len()).map(|idx| idx as f32).collect())
+    }
+
+    fn failing_reranker(_: &str, _: &[String]) -> Result<Vec<f32>, EmbeddingError> {
+        Err(EmbeddingError::Message("model not available".to_string()))
+    }
+
+    fn message(role: DBMessageRole, content: &str) -> ApiMessage {
+        ApiMessage {
+            role,
+            content: content.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_complete_with_profile() {
+        let llm = MockAdapter::new(Model::Qwen).template("{question} <- {context}");
+        let pipeline =
+            RagPipeline::new(&StaticVectorStorage, &llm, "catalog").embedder(fixed_embedding);
+
+        let messages = vec![
+            message(DBMessageRole::User, "What is COSC 30603?"),
+            message(DBMessageRole::Assistant, "Data Structures."),
+            message(DBMessageRole::User, "Who teaches it?"),
+        ];
+
+        let query = pipeline.build_query(&messages).unwrap();
+        assert_eq!(query.question, "Who teaches it?");
+        assert_eq!(
+            query.search_queries,
+            ["What is COSC 30603? Who teaches it?"]
+        );
+
+        // the profile search returns the same five points as the query search
+        let retrieval = pipeline.retrieve(&query, Some("Junior")).await.unwrap();
+        assert_eq!(retrieval.points.len(), 5);
+        assert_eq!(retrieval.timings.rerank_ms, None);
+
+        let completion = pipeline
+            .complete(messages, Some("Junior".to_string()))
+            .await
+            .unwrap();
+        assert!(completion
+            .response
+            .content
+            .starts_with("Who teaches it? <- [1] catalog\n[chunk 0]\n\n[2] catalog\n[chunk 1]"));
+        assert_eq!(completion.sources.len(), 5);
+        assert_eq!(completion.sources[1].index, 2);
+        assert_eq!(completion.sources[1].chunk_id, "1");
+    }
+
+    #[tokio::test]
+    async fn test_prompt_budget() {
+        let llm = MockAdapter::new(Model::Qwen);
+        let budget = PromptBudget {
+            profile: 5,
+            counter: TokenCounter::new(4.0),
+            ..PromptBudget::default()
+        };
+        let pipeline = RagPipeline::new(&StaticVectorStorage, &llm, "catalog")
+            .embedder(fixed_embedding)
+            .budget(budget);
+        let query = pipeline
+            .build_query(&[message(DBMessageRole::User, "What is COSC 30603?")])
+            .unwrap();
+        let points = pipeline.retrieve(&query, None).await.unwrap().points;
+
+        // every block takes 6 tokens, only whole blocks are added after the first
+        let (context, sources) = pipeline.assemble_context(&points, 13);
+        assert_eq!(sources.len(), 2);
+        assert!(context.ends_with("[2] catalog\n[chunk 1]\n\n"));
+
+        let (context, sources) = pipeline.assemble_context(&points, 4);
+        assert_eq!(sources.len(), 1);
+        assert_eq!(context, "[1] catalog\n\n");
+
+        assert_eq!(
+            pipeline.fit_profile(Some("Junior, Computer Science BS".to_string())),
+            Some("Junior, Computer".to_string())
+        );
+        assert_eq!(
+            pipeline.fit_profile(Some("Junior".to_string())),
+            Some("Junior".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_long_question() {
+        let llm = MockAdapter::new(Model::Qwen).template("{question}");
+        let counter = TokenCounter::new(4.0);
+        let budget = PromptBudget {
+            instruction: counter.count(Instruction::RAG.text()) + 4,
+            profile: 0,
+            context: 24,
+            history: 10,
+            counter,
+            ..PromptBudget::default()
+        };
+        let pipeline = RagPipeline::new(&StaticVectorStorage, &llm, "catalog")
+            .embedder(fixed_embedding)
+            .budget(budget);
+
+        // 14 tokens, 22 with the message overhead, the 12 over the history budget shrink the context
+        let completion = pipeline
+            .complete(
+                vec![message(
+                    DBMessageRole::User,
+                    "Can I take COSC 30603 and COSC 40003 in the same term?",
+                )],
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(completion.response.content, "Can I take COSC");
+        assert_eq!(completion.sources.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_score_threshold() {
+        let llm = MockAdapter::new(Model::Qwen).template("{context}");
+        let messages = || vec![message(DBMessageRole::User, "Who teaches COSC 30603?")];
+
+        let pipeline = RagPipeline::new(&StaticVectorStorage, &llm, "catalog")
+            .embedder(fixed_embedding)
+            .query_options(VectorQueryOptions::default().score_threshold(0.85));
+        let completion = pipeline.complete(messages(), None).await.unwrap();
+        assert_eq!(completion.sources.len(), 2);
+
+        // nothing above the threshold, the prompt says so instead of passing weak matches
+        let pipeline = RagPipeline::new(&StaticVectorStorage, &llm, "catalog")
+            .embedder(fixed_embedding)
+            .query_options(VectorQueryOptions::default().score_threshold(1.5));
+        let completion = pipeline.complete(messages(), None).await.unwrap();
+        assert!(completion.sources.is_empty());
+        assert_eq!(completion.response.content, NO_RELEVANT_CONTEXT);
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_query() {
+        let llm = MockAdapter::new(Model::Qwen)
+            .rewrite("1. COSC 30603 prerequisites\n2. \"COSC 30603 instructor\"\n\nCOSC 30603 prerequisites");
+        let pipeline = RagPipeline::new(&StaticVectorStorage, &llm, "catalog")
+            .embedder(fixed_embedding)
+            .rewrite(true);
+        let messages = vec![
+            message(DBMessageRole::User, "What is COSC 30603?"),
+            message(DBMessageRole::Assistant, "Data Structures."),
+            message(DBMessageRole::User, "What about the prerequisites and who teaches it?"),
+        ];
+
+        let query = pipeline.rewrite_query(&messages).await.unwrap();
+        assert_eq!(query.question, "What about the prerequisites and who teaches it?");
+        assert_eq!(
+            query.search_queries,
+            ["COSC 30603 prerequisites", "COSC 30603 instructor"]
+        );
+
+        // both queries find the same points, they are merged instead of repeated
+        let completion = pipeline.complete(messages.clone(), None).await.unwrap();
+        assert_eq!(completion.sources.len(), 5);
+        assert_eq!(completion.search_queries.len(), 2);
+        assert!(completion.timings.rewrite_ms.is_some());
+
+        // a first question is searched as it is
+        let query = pipeline
+            .rewrite_query(&[message(DBMessageRole::User, "What is COSC 30603?")])
+            .await
+            .unwrap();
+        assert_eq!(query.search_queries, ["What is COSC 30603?"]);
+
+        // a slow rewrite is given up on, the question is searched as it is
+        let llm = MockAdapter::new(Model::Qwen)
+            .rewrite("COSC 30603 prerequisites")
+            .latency(Duration::from_millis(50));
+        let pipeline = RagPipeline::new(&StaticVectorStorage, &llm, "catalog")
+            .embedder(fixed_embedding)
+            .rewrite(true)
+            .rewrite_timeout(Duration::from_millis(10));
+        let query = pipeline.rewrite_query(&messages).await.unwrap();
+        assert_eq!(
+            query.search_queries,
+            ["What is COSC 30603? What about the prerequisites and who teaches it?"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_query() {
+        let llm = MockAdapter::new(Model::Qwen);
+        let pipeline = RagPipeline::new(&StaticVectorStorage, &llm, "catalog")
+            .embedder(fixed_embedding)
+            .query_options(VectorQueryOptions::default().mode(SearchMode::Hybrid));
+
+        // the sparse query vector is computed next to the dense one
+        let completion = pipeline
+            .complete(vec![message(DBMessageRole::User, "COSC 30603")], None)
+            .await
+            .unwrap();
+        assert_eq!(completion.sources.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_rerank() {
+        let llm = MockAdapter::new(Model::Qwen);
+        let options = RerankOptions::default().candidates(7).top_n(3);
+        let pipeline = RagPipeline::new(&StaticVectorStorage, &llm, "catalog")
+            .embedder(fixed_embedding)
+            .reranker(reverse_reranker)
+            .rerank(Some(options.clone()));
+        let query = pipeline
+            .build_query(&[message(DBMessageRole::User, "What is COSC 30603?")])
+            .unwrap();
+
+        // all seven candidates are fetched instead of the default five, the last ones win
+        let retrieval = pipeline.retrieve(&query, None).await.unwrap();
+        let ids: Vec<&str> = retrieval.points.iter().map(|p| p.uuid.as_str()).collect();
+        assert_eq!(ids, ["6", "5", "4"]);
+        assert_eq!(retrieval.points[0].score, Some(6.0));
+        assert!(retrieval.timings.rerank_ms.is_some());
+
+        // without the reranker the best candidates of the vector search are kept
+        let pipeline = RagPipeline::new(&StaticVectorStorage, &llm, "catalog")
+            .embedder(fixed_embedding)
+            .reranker(failing_reranker)
+            .rerank(Some(options.clone()));
+        let retrieval = pipeline.retrieve(&query, None).await.unwrap();
+        let ids: Vec<&str> = retrieval.points.iter().map(|p| p.uuid.as_str()).collect();
+        assert_eq!(ids, ["0", "1", "2"]);
+
+        // MMR selects from the reranked candidates, the points have no embeddings to compare so
+        // only their relevance counts
+        let pipeline = RagPipeline::new(&StaticVectorStorage, &llm, "catalog")
+            .embedder(fixed_embedding)
+            .reranker(reverse_reranker)
+            .rerank(Some(options))
+            .mmr(Some(MmrOptions::default()));
+        let retrieval = pipeline.retrieve(&query, None).await.unwrap();
+        let ids: Vec<&str> = retrieval.points.iter().map(|p| p.uuid.as_str()).collect();
+        assert_eq!(ids, ["6", "5", "4"]);
+        assert!(retrieval.timings.mmr_ms.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_missing_question() {
+        let llm = MockAdapter::new(Model::Qwen);
+        let pipeline =
+            RagPipeline::new(&StaticVectorStorage, &llm, "catalog").embedder(fixed_embedding);
+
+        let result = pipeline
+            .complete(vec![message(DBMessageRole::Assistant, "Hello!")], None)
+            .await;
+
+        assert!(matches!(result, Err(RagError::MissingQuestion)));
+    }
+}
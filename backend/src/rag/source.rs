@@ -0,0 +1,77 @@
+use crate::storage::vector::VectorDataPoint;
+use serde::{Deserialize, Serialize};
+
+// maximum number of characters of a chunk returned as a snippet
+const SNIPPET_LENGTH: usize = 200;
+
+/// A retrieved chunk that was placed in the prompt, `index` matches the `[n]` citation marker.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Source {
+    pub index: usize,
+    // file the chunk was created from
+    pub document: String,
+    // point id of the chunk in the vector storage
+    pub chunk_id: String,
+    pub score: Option<f32>,
+    pub snippet: String,
+}
+
+impl Source {
+    pub fn from_point(index: usize, point: &VectorDataPoint) -> Self {
+        Self {
+            index,
+            document: document_name(&point.name).to_string(),
+            chunk_id: point.uuid.clone(),
+            score: point.score,
+            snippet: snippet(&point.content),
+        }
+    }
+}
+
+// point names are compiled as '{file_name}_{chunk_idx}', see vectorization::utils::compile_vectors
+pub fn document_name(point_name: &str) -> &str {
+    match point_name.rsplit_once('_') {
+        Some((document, idx)) if idx.parse::<usize>().is_ok() => document,
+        _ => point_name,
+    }
+}
+
+fn snippet(content: &str) -> String {
+    let content = content.trim();
+
+    if content.chars().count() <= SNIPPET_LENGTH {
+        return content.to_string();
+    }
+
+    let truncated: String = content.chars().take(SNIPPET_LENGTH).collect();
+
+    // avoid cutting a word in half when possible
+    let truncated = match truncated.rsplit_once(char::is_whitespace) {
+        Some((words, _)) => words,
+        None => truncated.as_str(),
+    };
+
+    format!("{}...", truncated.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rag::source::{document_name, snippet};
+
+    #[test]
+    fn test_document_name() {
+        assert_eq!(document_name("Common_Dataset.md_12"), "Common_Dataset.md");
+        assert_eq!(document_name("Common_Dataset.md"), "Common_Dataset.md");
+    }
+
+    #[test]
+    fn test_snippet_truncates_on_word_boundary() {
+        let content = "word ".repeat(100);
+
+        let result = snippet(&content);
+
+        assert!(result.ends_with("word..."));
+        assert!(result.len() <= 203);
+        assert_eq!(snippet("COSC 30603"), "COSC 30603");
+    }
+}
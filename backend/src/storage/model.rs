@@ -1,5 +1,7 @@
+use crate::rag::source::Source;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
 use std::fmt;
 
 #[derive(sqlx::Type, Debug, Serialize, Deserialize)]
@@ -19,17 +21,27 @@ impl fmt::Display for DBMessageRole {
     }
 }
 
-#[derive(sqlx::Type, Debug, Serialize, Deserialize)]
+#[derive(sqlx::Type, Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
 #[sqlx(type_name = "chat.conversation_status", rename_all = "lowercase")]
 pub enum DBConversationStatus {
+    #[serde(alias = "active")]
     Active,
+    #[serde(alias = "archived")]
     Archived,
+    #[serde(alias = "starred")]
     Starred,
+    #[serde(alias = "system")]
     System,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[derive(Clone)]
+impl DBConversationStatus {
+    /// `system` conversations are managed by the server, users can neither set nor leave it.
+    pub fn is_user_settable(&self) -> bool {
+        !matches!(self, DBConversationStatus::System)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DBUser {
     pub id: i32,
     pub student_id: Option<String>,
@@ -41,6 +53,7 @@ pub struct DBUser {
     pub last_login_at: DateTime<Utc>,
     pub university: Option<String>,
     pub academic_profile: Option<String>,
+    pub is_admin: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -48,6 +61,8 @@ pub struct DBConversation {
     pub id: i32,
     pub owner_id: i32,
     pub title: String,
+    // the title was generated by the server, false for titles the owner set
+    pub title_generated: bool,
     pub last_message_at: DateTime<Utc>,
     pub status: DBConversationStatus,
 }
@@ -56,13 +71,276 @@ pub struct DBConversation {
 pub struct DBMessage {
     pub id: i32,
     pub conversation_id: i32,
+    // previous message in the branch, None for the first message
+    pub parent_id: Option<i32>,
+    pub content: String,
+    pub role: DBMessageRole,
+    pub created_at: DateTime<Utc>,
+    // how an assistant message was generated, None on user messages
+    pub model: Option<String>,
+    pub prompt_tokens: Option<i32>,
+    pub completion_tokens: Option<i32>,
+    // milliseconds from receiving the question until the answer was complete
+    pub latency_ms: Option<i32>,
+    pub template_version: Option<String>,
+    pub sources: Option<Json<Vec<Source>>>,
+}
+
+/// How an answer was generated, stored with it by RelationalStorage::create_answer.
+#[derive(Debug, Clone)]
+pub struct DBGeneration {
+    pub model: String,
+    pub prompt_tokens: Option<i32>,
+    pub completion_tokens: Option<i32>,
+    pub latency_ms: Option<i32>,
+    pub template_version: String,
+    pub sources: Vec<Source>,
+    pub search_queries: Vec<String>,
+}
+
+#[derive(sqlx::Type, Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "chat.feedback_rating", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DBFeedbackRating {
+    Up,
+    Down,
+}
+
+#[derive(sqlx::Type, Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "chat.feedback_category", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DBFeedbackCategory {
+    Incorrect,
+    Outdated,
+    Unhelpful,
+    Unsafe,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DBMessageFeedback {
+    pub id: i32,
+    pub message_id: i32,
+    pub user_id: i32,
+    pub rating: DBFeedbackRating,
+    pub category: Option<DBFeedbackCategory>,
+    pub comment: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A feedback row joined with the answer it rates and the question that was asked.
+#[derive(Serialize, Debug)]
+pub struct DBFeedbackExport {
+    pub feedback_id: i32,
+    pub rating: DBFeedbackRating,
+    pub category: Option<DBFeedbackCategory>,
+    pub comment: Option<String>,
+    // when the rating was last given or changed
+    pub rated_at: DateTime<Utc>,
+    pub user_id: i32,
+    pub conversation_id: i32,
+    pub message_id: i32,
+    // None when the answer has no parent, which only happens for hand-inserted messages
+    pub prompt: Option<String>,
+    pub answer: String,
+    pub model: Option<String>,
+    // the rag::source::Source list stored with the answer
+    pub sources: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DBConversationShare {
+    pub id: i32,
+    pub conversation_id: i32,
+    pub token: String,
+    pub snapshot_leaf_id: i32,
+    pub same_university: bool,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// An LLM summary that replaces the branch up to `through_message_id` in prompts.
+#[derive(Debug)]
+pub struct DBConversationSummary {
+    pub id: i32,
+    pub conversation_id: i32,
+    pub through_message_id: i32,
     pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A share that can currently be viewed, with what is needed to check who may view it.
+#[derive(Debug)]
+pub struct DBSharedConversation {
+    pub snapshot_leaf_id: i32,
+    pub same_university: bool,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub title: String,
+    pub owner_university: Option<String>,
+}
+
+/// A message found by a conversation search, with the conversation it belongs to.
+#[derive(Debug)]
+pub struct DBMessageMatch {
+    pub conversation_id: i32,
+    pub title: String,
+    pub title_generated: bool,
+    pub status: DBConversationStatus,
+    pub last_message_at: DateTime<Utc>,
+    pub message_id: i32,
     pub role: DBMessageRole,
     pub created_at: DateTime<Utc>,
+    // the matching part of the message, HTML-escaped, full-text matches are wrapped in <mark></mark>
+    pub snippet: String,
+}
+
+#[derive(Debug)]
+pub struct DBMessageEmbedding {
+    pub message_id: i32,
+    pub embedding: Vec<f32>,
+}
+
+pub const DEFAULT_PAGE_LIMIT: usize = 50;
+pub const MAX_PAGE_LIMIT: usize = 100;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DBSortOrder {
+    Asc,
+    Desc,
 }
 
+/// Query parameters of paginated listings.
 #[derive(Deserialize, Debug, Default)]
 pub struct DBFilterOptions {
-    pub page: Option<usize>,
+    // `next_cursor` of the previous page, omitted for the first page
+    pub cursor: Option<String>,
     pub limit: Option<usize>,
+    // only applies to conversations
+    pub status: Option<DBConversationStatus>,
+    pub sort: Option<DBSortOrder>,
+}
+
+/// Position after the last row of a page, rows are ordered by timestamp and then id
+/// so rows sharing a timestamp are neither skipped nor repeated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DBCursor {
+    pub timestamp: DateTime<Utc>,
+    pub id: i32,
+}
+
+/// Validated pagination parameters handed to the storage layer.
+#[derive(Debug, Clone, Copy)]
+pub struct DBPageQuery {
+    pub cursor: Option<DBCursor>,
+    pub limit: usize,
+    pub sort: DBSortOrder,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DBPage<T> {
+    pub items: Vec<T>,
+    // None on the last page
+    pub next_cursor: Option<String>,
+}
+
+impl DBFilterOptions {
+    pub fn page_query(&self, default_sort: DBSortOrder) -> Result<DBPageQuery, String> {
+        let cursor = match &self.cursor {
+            Some(cursor) => Some(cursor.parse::<DBCursor>()?),
+            None => None,
+        };
+
+        Ok(DBPageQuery {
+            cursor,
+            limit: self
+                .limit
+                .unwrap_or(DEFAULT_PAGE_LIMIT)
+                .clamp(1, MAX_PAGE_LIMIT),
+            sort: self.sort.unwrap_or(default_sort),
+        })
+    }
+}
+
+// cursors are opaque to clients, the format is '{timestamp in microseconds}_{id}'
+impl fmt::Display for DBCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.timestamp.timestamp_micros(), self.id)
+    }
+}
+
+impl std::str::FromStr for DBCursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid cursor: {}", s);
+
+        let (micros, id) = s.split_once('_').ok_or_else(invalid)?;
+        let micros = micros.parse::<i64>().map_err(|_| invalid())?;
+
+        Ok(DBCursor {
+            timestamp: DateTime::from_timestamp_micros(micros).ok_or_else(invalid)?,
+            id: id.parse::<i32>().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl<T> DBPage<T> {
+    /// Builds a page from `limit + 1` fetched rows, the extra row only signals another page.
+    pub fn from_rows(mut rows: Vec<T>, limit: usize, cursor: impl Fn(&T) -> DBCursor) -> Self {
+        let next_cursor = if rows.len() > limit {
+            rows.truncate(limit);
+            rows.last().map(|row| cursor(row).to_string())
+        } else {
+            None
+        };
+
+        DBPage {
+            items: rows,
+            next_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::model::{DBCursor, DBFilterOptions, DBPage, DBSortOrder, MAX_PAGE_LIMIT};
+    use chrono::{DateTime, Utc};
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = DBCursor {
+            timestamp: DateTime::<Utc>::from_timestamp_micros(1_740_000_000_123_456).unwrap(),
+            id: 42,
+        };
+
+        assert_eq!(cursor.to_string().parse::<DBCursor>(), Ok(cursor));
+        assert!("42".parse::<DBCursor>().is_err());
+        assert!("abc_42".parse::<DBCursor>().is_err());
+    }
+
+    #[test]
+    fn test_page_from_rows() {
+        let cursor = |id: &i32| DBCursor {
+            timestamp: DateTime::<Utc>::from_timestamp_micros(0).unwrap(),
+            id: *id,
+        };
+
+        let page = DBPage::from_rows(vec![1, 2, 3], 2, cursor);
+        assert_eq!(page.items, vec![1, 2]);
+        assert_eq!(page.next_cursor.as_deref(), Some("0_2"));
+
+        let page = DBPage::from_rows(vec![1, 2], 2, cursor);
+        assert_eq!(page.next_cursor, None);
+
+        let options = DBFilterOptions {
+            limit: Some(10_000),
+            ..Default::default()
+        };
+        let query = options.page_query(DBSortOrder::Desc).unwrap();
+        assert_eq!(query.limit, MAX_PAGE_LIMIT);
+        assert_eq!(query.sort, DBSortOrder::Desc);
+    }
 }
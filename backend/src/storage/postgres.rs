@@ -1,7 +1,11 @@
-use crate::storage::model::DBConversationStatus;
+use crate::storage::model::{DBConversationStatus, DBCursor, DBPage, DBPageQuery, DBSortOrder};
+use crate::rag::source::Source;
 use crate::storage::model::{DBConversation, DBMessage, DBMessageRole, DBUser};
+use crate::storage::model::{DBFeedbackCategory, DBFeedbackExport, DBFeedbackRating, DBGeneration, DBMessageFeedback};
+use crate::storage::model::{DBConversationShare, DBConversationSummary, DBMessageEmbedding, DBMessageMatch, DBSharedConversation};
 use chrono::{DateTime, Utc};
 use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::types::Json;
 use std::error::Error;
 
 pub struct RelationalStorage {
@@ -18,6 +22,10 @@ impl RelationalStorage {
         Ok(RelationalStorage { pool })
     }
 
+    pub fn from_pool(pool: PgPool) -> Self {
+        RelationalStorage { pool }
+    }
+
     pub async fn create_user(
         &self,
         email: &str,
@@ -28,7 +36,7 @@ impl RelationalStorage {
             r#"
             INSERT INTO chat.users (email, password_hash)
             VALUES ($1, $2)
-            RETURNING id, student_id, email, password_hash, first_name, last_name, created_at, last_login_at, university, academic_profile
+            RETURNING id, student_id, email, password_hash, first_name, last_name, created_at, last_login_at, university, academic_profile, is_admin
             "#,
             email,
             password_hash
@@ -51,7 +59,7 @@ impl RelationalStorage {
             UPDATE chat.users
             SET student_id = $2, first_name = $3, last_name = $4, university = $5
             WHERE id = $1
-            RETURNING id, student_id, email, password_hash, first_name, last_name, created_at, last_login_at, university, academic_profile
+            RETURNING id, student_id, email, password_hash, first_name, last_name, created_at, last_login_at, university, academic_profile, is_admin
             "#,
             id,
             student_id,
@@ -97,7 +105,7 @@ impl RelationalStorage {
             r#"
             INSERT INTO chat.conversations (owner_id, title)
             VALUES ($1, $2)
-            RETURNING id, owner_id, title, last_message_at, status as "status!: DBConversationStatus"
+            RETURNING id, owner_id, title, title_generated, last_message_at, status as "status!: DBConversationStatus"
             "#,
             owner_id,
             title
@@ -106,61 +114,172 @@ impl RelationalStorage {
             .await
     }
 
+    /// Updates the given fields, `None` keeps the current value. A new title counts as set by the
+    /// owner, so it is never replaced by a generated one.
     pub async fn update_conversation(
         &self,
         conversation_id: &i32,
-        title: &str,
+        title: Option<&str>,
+        status: Option<DBConversationStatus>,
     ) -> Result<DBConversation, sqlx::Error> {
         sqlx::query_as!(
             DBConversation,
             r#"
             UPDATE chat.conversations
-            SET title = $2
+            SET title = COALESCE($2, title),
+                title_generated = title_generated AND $2::varchar IS NULL,
+                status = COALESCE($3, status)
             WHERE id = $1
-            RETURNING id, owner_id, title, last_message_at, status as "status!: DBConversationStatus"
+            RETURNING id, owner_id, title, title_generated, last_message_at, status as "status!: DBConversationStatus"
             "#,
             conversation_id,
-            title
+            title,
+            status as Option<DBConversationStatus>
         )
             .fetch_one(&self.pool)
             .await
     }
 
+    /// Replaces the placeholder title with a generated one, returns false without changing
+    /// anything if the title was generated already or the owner set one in the meantime.
+    pub async fn set_generated_title(
+        &self,
+        conversation_id: i32,
+        placeholder: &str,
+        title: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE chat.conversations
+            SET title = $3, title_generated = true
+            WHERE id = $1 AND title = $2 AND NOT title_generated AND deleted_at IS NULL
+            "#,
+            conversation_id,
+            placeholder,
+            title
+        )
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Hides the conversation from its owner, it is removed for good by `purge_deleted_conversations`.
+    pub async fn soft_delete_conversation(&self, conversation_id: &i32) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE chat.conversations
+            SET deleted_at = CURRENT_TIMESTAMP
+            WHERE id = $1 AND deleted_at IS NULL
+            "#,
+            conversation_id
+        )
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Removes conversations soft-deleted before `deleted_before` together with their messages,
+    /// returns the number of removed conversations.
+    pub async fn purge_deleted_conversations(
+        &self,
+        deleted_before: DateTime<Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM chat.messages
+            WHERE conversation_id IN (
+                SELECT id FROM chat.conversations WHERE deleted_at < $1
+            )
+            "#,
+            deleted_before
+        )
+            .execute(&mut *transaction)
+            .await?;
+
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM chat.conversations
+            WHERE deleted_at < $1
+            "#,
+            deleted_before
+        )
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// One page of the user's conversations ordered by `last_message_at`, optionally by status.
     pub async fn get_user_conversations(
         &self,
         user_id: i32,
-    ) -> Result<Vec<DBConversation>, sqlx::Error> {
-        sqlx::query_as!(
+        status: Option<DBConversationStatus>,
+        page: DBPageQuery,
+    ) -> Result<DBPage<DBConversation>, sqlx::Error> {
+        let ascending = page.sort == DBSortOrder::Asc;
+        let cursor_time = page.cursor.map(|cursor| cursor.timestamp);
+        let cursor_id = page.cursor.map(|cursor| cursor.id);
+
+        // one extra row tells whether there is another page
+        let rows = sqlx::query_as!(
             DBConversation,
             r#"
-            SELECT id, owner_id, title, last_message_at as "last_message_at:DateTime<Utc>", status as "status:_"
+            SELECT id, owner_id, title, title_generated, last_message_at as "last_message_at:DateTime<Utc>", status as "status:_"
             FROM chat.conversations
             WHERE owner_id = $1
-            ORDER BY last_message_at
+              AND deleted_at IS NULL
+              AND ($2::chat.conversation_status IS NULL OR status = $2)
+              AND ($3::timestamptz IS NULL OR CASE
+                  WHEN $5 THEN (last_message_at, id) > ($3, $4)
+                  ELSE (last_message_at, id) < ($3, $4)
+              END)
+            ORDER BY
+                CASE WHEN $5 THEN last_message_at END ASC,
+                CASE WHEN $5 THEN id END ASC,
+                last_message_at DESC,
+                id DESC
+            LIMIT $6
             "#,
-            user_id
+            user_id,
+            status as Option<DBConversationStatus>,
+            cursor_time,
+            cursor_id,
+            ascending,
+            (page.limit + 1) as i64
         )
             .fetch_all(&self.pool)
-            .await
+            .await?;
+
+        Ok(DBPage::from_rows(rows, page.limit, |conversation| DBCursor {
+            timestamp: conversation.last_message_at,
+            id: conversation.id,
+        }))
     }
 
     pub async fn get_conversation_by_id(
         &self,
         conversation_id: i32,
-    ) -> Result<Vec<DBConversation>, sqlx::Error> {
+    ) -> Result<Option<DBConversation>, sqlx::Error> {
         sqlx::query_as!(
             DBConversation,
             r#"
-            SELECT id, owner_id, title, last_message_at as "last_message_at:DateTime<Utc>", status as "status:_"
+            SELECT id, owner_id, title, title_generated, last_message_at as "last_message_at:DateTime<Utc>", status as "status:_"
             FROM chat.conversations
-            WHERE id = $1
+            WHERE id = $1 AND deleted_at IS NULL
             "#,
             conversation_id
         )
-            .fetch_all(&self.pool)
+            .fetch_optional(&self.pool)
             .await
     }
 
+    /// Appends a message to the active branch of the conversation and makes it the active leaf.
     pub async fn create_message(
         &self,
         conversation_id: i32,
@@ -171,18 +290,53 @@ impl RelationalStorage {
             DBMessage,
             r#"
                 WITH new_message AS (
-                INSERT INTO chat.messages (conversation_id, content, role)
-                VALUES ($1, $2, $3)
-                RETURNING id, conversation_id, content, role as "role!: DBMessageRole", created_at
+                INSERT INTO chat.messages (conversation_id, parent_id, content, role)
+                VALUES ($1, (SELECT active_leaf_id FROM chat.conversations WHERE id = $1), $2, $3)
+                RETURNING id, conversation_id, parent_id, content, role as "role!: DBMessageRole", created_at,
+                    model, prompt_tokens, completion_tokens, latency_ms, template_version, sources as "sources: Json<Vec<Source>>"
+            ),
+            update_conversation AS (
+                UPDATE chat.conversations
+                SET last_message_at = CURRENT_TIMESTAMP, active_leaf_id = (SELECT id FROM new_message)
+                WHERE id = $1
+            )
+            SELECT * FROM new_message
+            "#,
+            conversation_id,
+            content,
+            role as DBMessageRole
+        )
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// Adds a message below `parent_id`, or as a new first message when it is `None`,
+    /// and makes it the active leaf. Used to start a new branch next to existing messages.
+    pub async fn create_message_with_parent(
+        &self,
+        conversation_id: i32,
+        parent_id: Option<i32>,
+        content: String,
+        role: DBMessageRole,
+    ) -> Result<DBMessage, sqlx::Error> {
+        sqlx::query_as!(
+            DBMessage,
+            r#"
+                WITH new_message AS (
+                INSERT INTO chat.messages (conversation_id, parent_id, content, role)
+                VALUES ($1, $2, $3, $4)
+                RETURNING id, conversation_id, parent_id, content, role as "role!: DBMessageRole", created_at,
+                    model, prompt_tokens, completion_tokens, latency_ms, template_version, sources as "sources: Json<Vec<Source>>"
             ),
             update_conversation AS (
                 UPDATE chat.conversations
-                SET last_message_at = CURRENT_TIMESTAMP
+                SET last_message_at = CURRENT_TIMESTAMP, active_leaf_id = (SELECT id FROM new_message)
                 WHERE id = $1
             )
             SELECT * FROM new_message
             "#,
             conversation_id,
+            parent_id,
             content,
             role as DBMessageRole
         )
@@ -190,19 +344,581 @@ impl RelationalStorage {
             .await
     }
 
-    pub async fn get_conversation_messages(
+    /// Stores a user message below `parent_id`, or as a new first message when it is `None`,
+    /// without moving the active leaf. The answer to it moves the leaf once it is stored, so a
+    /// question whose answer fails is kept without ending the active branch in an unanswered turn.
+    pub async fn create_question(
+        &self,
+        conversation_id: i32,
+        parent_id: Option<i32>,
+        content: String,
+    ) -> Result<DBMessage, sqlx::Error> {
+        sqlx::query_as!(
+            DBMessage,
+            r#"
+            INSERT INTO chat.messages (conversation_id, parent_id, content, role)
+            VALUES ($1, $2, $3, 'user')
+            RETURNING id, conversation_id, parent_id, content, role as "role!: DBMessageRole", created_at,
+                model, prompt_tokens, completion_tokens, latency_ms, template_version, sources as "sources: Json<Vec<Source>>"
+            "#,
+            conversation_id,
+            parent_id,
+            content
+        )
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// Stores the answer to the user message `question_id` together with how it was generated.
+    /// The answer becomes the active leaf if the active leaf is still `expected_leaf_id`, the leaf
+    /// when the question was asked, so a branch selected while the answer streamed is kept.
+    pub async fn create_answer(
+        &self,
+        conversation_id: i32,
+        question_id: i32,
+        content: String,
+        generation: &DBGeneration,
+        expected_leaf_id: Option<i32>,
+    ) -> Result<DBMessage, sqlx::Error> {
+        sqlx::query_as!(
+            DBMessage,
+            r#"
+                WITH new_message AS (
+                INSERT INTO chat.messages (conversation_id, parent_id, content, role, model, prompt_tokens,
+                    completion_tokens, latency_ms, template_version, sources, search_queries)
+                VALUES ($1, $2, $3, 'assistant', $4, $5, $6, $7, $8, $9, $10)
+                RETURNING id, conversation_id, parent_id, content, role as "role!: DBMessageRole", created_at,
+                    model, prompt_tokens, completion_tokens, latency_ms, template_version, sources as "sources: Json<Vec<Source>>"
+            ),
+            update_conversation AS (
+                UPDATE chat.conversations
+                SET last_message_at = CURRENT_TIMESTAMP,
+                    active_leaf_id = CASE
+                        WHEN active_leaf_id IS NOT DISTINCT FROM $11 THEN (SELECT id FROM new_message)
+                        ELSE active_leaf_id
+                    END
+                WHERE id = $1
+            )
+            SELECT * FROM new_message
+            "#,
+            conversation_id,
+            question_id,
+            content,
+            generation.model,
+            generation.prompt_tokens,
+            generation.completion_tokens,
+            generation.latency_ms,
+            generation.template_version,
+            Json(&generation.sources) as _,
+            &generation.search_queries,
+            expected_leaf_id
+        )
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    pub async fn get_message_by_id(
+        &self,
+        message_id: i32,
+    ) -> Result<Option<DBMessage>, sqlx::Error> {
+        sqlx::query_as!(
+            DBMessage,
+            r#"
+            SELECT id, conversation_id, parent_id, content, role as "role!: DBMessageRole", created_at as "created_at:DateTime<Utc>",
+                model, prompt_tokens, completion_tokens, latency_ms, template_version, sources as "sources: Json<Vec<Source>>"
+            FROM chat.messages
+            WHERE id = $1
+            "#,
+            message_id
+        )
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    /// One page of the active branch of a conversation ordered by `created_at`.
+    pub async fn get_conversation_messages_page(
+        &self,
+        conversation_id: i32,
+        page: DBPageQuery,
+    ) -> Result<DBPage<DBMessage>, sqlx::Error> {
+        let ascending = page.sort == DBSortOrder::Asc;
+        let cursor_time = page.cursor.map(|cursor| cursor.timestamp);
+        let cursor_id = page.cursor.map(|cursor| cursor.id);
+
+        let rows = sqlx::query_as!(
+            DBMessage,
+            r#"
+            WITH RECURSIVE branch AS (
+                SELECT m.id, m.conversation_id, m.parent_id, m.content, m.role, m.created_at, m.model, m.prompt_tokens,
+                    m.completion_tokens, m.latency_ms, m.template_version, m.sources
+                FROM chat.messages m
+                JOIN chat.conversations c ON c.active_leaf_id = m.id
+                WHERE c.id = $1
+                UNION ALL
+                SELECT m.id, m.conversation_id, m.parent_id, m.content, m.role, m.created_at, m.model, m.prompt_tokens,
+                    m.completion_tokens, m.latency_ms, m.template_version, m.sources
+                FROM chat.messages m
+                JOIN branch b ON m.id = b.parent_id
+            )
+            SELECT id as "id!", conversation_id as "conversation_id!", parent_id, content as "content!",
+                role as "role!: DBMessageRole", created_at as "created_at!:DateTime<Utc>", model, prompt_tokens, completion_tokens, latency_ms, template_version, sources as "sources: Json<Vec<Source>>"
+            FROM branch
+            WHERE ($2::timestamptz IS NULL OR CASE
+                  WHEN $4 THEN (created_at, id) > ($2, $3)
+                  ELSE (created_at, id) < ($2, $3)
+              END)
+            ORDER BY
+                CASE WHEN $4 THEN created_at END ASC,
+                CASE WHEN $4 THEN id END ASC,
+                created_at DESC,
+                id DESC
+            LIMIT $5
+            "#,
+            conversation_id,
+            cursor_time,
+            cursor_id,
+            ascending,
+            (page.limit + 1) as i64
+        )
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(DBPage::from_rows(rows, page.limit, |message| DBCursor {
+            timestamp: message.created_at,
+            id: message.id,
+        }))
+    }
+
+    /// The branch ending at `message_id`, from the first message down to and including it.
+    pub async fn get_branch(&self, message_id: i32) -> Result<Vec<DBMessage>, sqlx::Error> {
+        sqlx::query_as!(
+            DBMessage,
+            r#"
+            WITH RECURSIVE branch AS (
+                SELECT id, conversation_id, parent_id, content, role, created_at, model, prompt_tokens,
+                    completion_tokens, latency_ms, template_version, sources, 0 AS depth
+                FROM chat.messages
+                WHERE id = $1
+                UNION ALL
+                SELECT m.id, m.conversation_id, m.parent_id, m.content, m.role, m.created_at, m.model, m.prompt_tokens,
+                    m.completion_tokens, m.latency_ms, m.template_version, m.sources, b.depth + 1
+                FROM chat.messages m
+                JOIN branch b ON m.id = b.parent_id
+            )
+            SELECT id as "id!", conversation_id as "conversation_id!", parent_id, content as "content!",
+                role as "role!: DBMessageRole", created_at as "created_at!:DateTime<Utc>", model, prompt_tokens, completion_tokens, latency_ms, template_version, sources as "sources: Json<Vec<Source>>"
+            FROM branch
+            ORDER BY depth DESC
+            "#,
+            message_id
+        )
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// The last message of the active branch, None while the conversation has no messages.
+    pub async fn get_active_leaf_id(&self, conversation_id: i32) -> Result<Option<i32>, sqlx::Error> {
+        Ok(sqlx::query_scalar!(
+            r#"
+            SELECT active_leaf_id FROM chat.conversations WHERE id = $1
+            "#,
+            conversation_id
+        )
+            .fetch_optional(&self.pool)
+            .await?
+            .flatten())
+    }
+
+    /// The active branch of a conversation, this is the history completions are answered with.
+    pub async fn get_active_branch(
         &self,
         conversation_id: i32,
+    ) -> Result<Vec<DBMessage>, sqlx::Error> {
+        match self.get_active_leaf_id(conversation_id).await? {
+            Some(leaf_id) => self.get_branch(leaf_id).await,
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Messages sharing the parent of `message`, including itself, oldest first.
+    pub async fn get_message_siblings(
+        &self,
+        message: &DBMessage,
     ) -> Result<Vec<DBMessage>, sqlx::Error> {
         sqlx::query_as!(
             DBMessage,
             r#"
-            SELECT id, conversation_id, content, role as "role!: DBMessageRole", created_at as "created_at:DateTime<Utc>"
+            SELECT id, conversation_id, parent_id, content, role as "role!: DBMessageRole", created_at as "created_at:DateTime<Utc>",
+                model, prompt_tokens, completion_tokens, latency_ms, template_version, sources as "sources: Json<Vec<Source>>"
             FROM chat.messages
-            WHERE conversation_id = $1
-            ORDER BY created_at ASC
+            WHERE conversation_id = $1 AND parent_id IS NOT DISTINCT FROM $2
+            ORDER BY created_at ASC, id ASC
+            "#,
+            message.conversation_id,
+            message.parent_id
+        )
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// Makes the branch through `message_id` active, following the newest reply at every
+    /// level below it. Returns the new active leaf.
+    pub async fn select_branch(
+        &self,
+        conversation_id: i32,
+        message_id: i32,
+    ) -> Result<i32, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            WITH RECURSIVE descendants AS (
+                SELECT id, 0 AS depth
+                FROM chat.messages
+                WHERE id = $2 AND conversation_id = $1
+                UNION ALL
+                SELECT child.id, d.depth + 1
+                FROM descendants d
+                CROSS JOIN LATERAL (
+                    SELECT id FROM chat.messages
+                    WHERE parent_id = d.id
+                    ORDER BY created_at DESC, id DESC
+                    LIMIT 1
+                ) child
+            )
+            UPDATE chat.conversations
+            SET active_leaf_id = (SELECT id FROM descendants ORDER BY depth DESC LIMIT 1)
+            WHERE id = $1
+            RETURNING active_leaf_id as "active_leaf_id!"
+            "#,
+            conversation_id,
+            message_id
+        )
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// The summary reaching furthest into the branch, `branch_ids` ordered oldest first.
+    pub async fn get_latest_summary(
+        &self,
+        branch_ids: &[i32],
+    ) -> Result<Option<DBConversationSummary>, sqlx::Error> {
+        sqlx::query_as!(
+            DBConversationSummary,
+            r#"
+            SELECT id, conversation_id, through_message_id, content, created_at
+            FROM chat.conversation_summaries
+            WHERE through_message_id = ANY($1)
+            ORDER BY array_position($1, through_message_id) DESC
+            LIMIT 1
+            "#,
+            branch_ids
+        )
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    /// Stores the summary of the branch up to `through_message_id`, replacing an existing one.
+    pub async fn create_summary(
+        &self,
+        conversation_id: i32,
+        through_message_id: i32,
+        content: &str,
+    ) -> Result<DBConversationSummary, sqlx::Error> {
+        sqlx::query_as!(
+            DBConversationSummary,
+            r#"
+            INSERT INTO chat.conversation_summaries (conversation_id, through_message_id, content)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (through_message_id) DO UPDATE
+            SET content = EXCLUDED.content, created_at = CURRENT_TIMESTAMP
+            RETURNING id, conversation_id, through_message_id, content, created_at
+            "#,
+            conversation_id,
+            through_message_id,
+            content
+        )
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// Shares the active branch of a conversation as it is now.
+    /// Returns None when the conversation has no messages.
+    pub async fn create_share(
+        &self,
+        conversation_id: i32,
+        token: &str,
+        same_university: bool,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<DBConversationShare>, sqlx::Error> {
+        sqlx::query_as!(
+            DBConversationShare,
+            r#"
+            INSERT INTO chat.conversation_shares (conversation_id, token, snapshot_leaf_id, same_university, expires_at)
+            SELECT id, $2, active_leaf_id, $3, $4
+            FROM chat.conversations
+            WHERE id = $1 AND active_leaf_id IS NOT NULL
+            RETURNING id, conversation_id, token, snapshot_leaf_id, same_university, created_at, expires_at, revoked_at
+            "#,
+            conversation_id,
+            token,
+            same_university,
+            expires_at
+        )
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    /// Shares of a conversation that were not revoked, newest first. Expired shares are included.
+    pub async fn get_conversation_shares(
+        &self,
+        conversation_id: i32,
+    ) -> Result<Vec<DBConversationShare>, sqlx::Error> {
+        sqlx::query_as!(
+            DBConversationShare,
+            r#"
+            SELECT id, conversation_id, token, snapshot_leaf_id, same_university, created_at, expires_at, revoked_at
+            FROM chat.conversation_shares
+            WHERE conversation_id = $1 AND revoked_at IS NULL
+            ORDER BY created_at DESC, id DESC
+            "#,
+            conversation_id
+        )
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// Revokes a share of the conversation, returns false when there was none to revoke.
+    pub async fn revoke_share(
+        &self,
+        conversation_id: i32,
+        share_id: i32,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE chat.conversation_shares
+            SET revoked_at = CURRENT_TIMESTAMP
+            WHERE id = $1 AND conversation_id = $2 AND revoked_at IS NULL
             "#,
+            share_id,
             conversation_id
+        )
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The share with `token` unless it expired, was revoked or its conversation was deleted.
+    pub async fn get_shared_conversation(
+        &self,
+        token: &str,
+    ) -> Result<Option<DBSharedConversation>, sqlx::Error> {
+        sqlx::query_as!(
+            DBSharedConversation,
+            r#"
+            SELECT s.snapshot_leaf_id, s.same_university, s.created_at, s.expires_at, c.title,
+                u.university as owner_university
+            FROM chat.conversation_shares s
+            JOIN chat.conversations c ON c.id = s.conversation_id
+            JOIN chat.users u ON u.id = c.owner_id
+            WHERE s.token = $1
+              AND s.revoked_at IS NULL
+              AND s.expires_at > CURRENT_TIMESTAMP
+              AND c.deleted_at IS NULL
+            "#,
+            token
+        )
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    /// Full-text search over the messages of the user's conversations, best matches first.
+    /// `query` uses the web search syntax of Postgres: quoted phrases, `or` and `-` to exclude words.
+    /// The snippet is HTML-escaped before the matches are wrapped in `<mark>` tags.
+    pub async fn search_messages(
+        &self,
+        user_id: i32,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<DBMessageMatch>, sqlx::Error> {
+        sqlx::query_as!(
+            DBMessageMatch,
+            r#"
+            WITH search AS (SELECT websearch_to_tsquery('english', $2) AS query)
+            SELECT c.id as conversation_id, c.title, c.title_generated, c.status as "status: DBConversationStatus",
+                c.last_message_at, m.id as message_id, m.role as "role: DBMessageRole", m.created_at,
+                ts_headline('english', replace(replace(replace(m.content, '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), search.query,
+                    'StartSel=<mark>, StopSel=</mark>, MinWords=15, MaxWords=35, MaxFragments=2') as "snippet!"
+            FROM chat.messages m
+            JOIN chat.conversations c ON c.id = m.conversation_id
+            CROSS JOIN search
+            WHERE c.owner_id = $1
+              AND c.deleted_at IS NULL
+              AND to_tsvector('english', m.content) @@ search.query
+            ORDER BY ts_rank(to_tsvector('english', m.content), search.query) DESC, m.created_at DESC
+            LIMIT $3
+            "#,
+            user_id,
+            query,
+            limit as i64
+        )
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// Messages of the user's conversations by id, in the order of `message_ids`.
+    /// The snippet is the beginning of the message, HTML-escaped like the full-text snippets.
+    pub async fn get_message_matches(
+        &self,
+        user_id: i32,
+        message_ids: &[i32],
+    ) -> Result<Vec<DBMessageMatch>, sqlx::Error> {
+        sqlx::query_as!(
+            DBMessageMatch,
+            r#"
+            SELECT c.id as conversation_id, c.title, c.title_generated, c.status as "status: DBConversationStatus",
+                c.last_message_at, m.id as message_id, m.role as "role: DBMessageRole", m.created_at,
+                replace(replace(replace(left(m.content, 200), '&', '&amp;'), '<', '&lt;'), '>', '&gt;') as "snippet!"
+            FROM chat.messages m
+            JOIN chat.conversations c ON c.id = m.conversation_id
+            WHERE c.owner_id = $1
+              AND c.deleted_at IS NULL
+              AND m.id = ANY($2)
+            ORDER BY array_position($2, m.id)
+            "#,
+            user_id,
+            message_ids
+        )
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// Embeddings of the `limit` most recently embedded messages in the user's conversations,
+    /// newest first.
+    pub async fn get_message_embeddings(
+        &self,
+        user_id: i32,
+        limit: i64,
+    ) -> Result<Vec<DBMessageEmbedding>, sqlx::Error> {
+        sqlx::query_as!(
+            DBMessageEmbedding,
+            r#"
+            SELECT m.id as message_id, m.embedding as "embedding!"
+            FROM chat.messages m
+            JOIN chat.conversations c ON c.id = m.conversation_id
+            WHERE c.owner_id = $1
+              AND c.deleted_at IS NULL
+              AND m.embedding IS NOT NULL
+            ORDER BY m.created_at DESC, m.id DESC
+            LIMIT $2
+            "#,
+            user_id,
+            limit
+        )
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// Up to `limit` messages without an embedding as `(id, content)`, oldest first.
+    pub async fn get_unembedded_messages(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<(i32, String)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT m.id, m.content
+            FROM chat.messages m
+            JOIN chat.conversations c ON c.id = m.conversation_id
+            WHERE m.embedding IS NULL
+              AND c.deleted_at IS NULL
+            ORDER BY m.id
+            LIMIT $1
+            "#,
+            limit
+        )
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(|row| (row.id, row.content)).collect())
+    }
+
+    pub async fn set_message_embeddings(
+        &self,
+        embeddings: &[(i32, Vec<f32>)],
+    ) -> Result<(), sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        for (message_id, embedding) in embeddings {
+            sqlx::query!(
+                r#"
+                UPDATE chat.messages SET embedding = $2 WHERE id = $1
+                "#,
+                message_id,
+                embedding
+            )
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        transaction.commit().await
+    }
+
+    /// Stores the user's rating of a message, replacing an earlier rating of the same message.
+    pub async fn upsert_message_feedback(
+        &self,
+        message_id: i32,
+        user_id: i32,
+        rating: DBFeedbackRating,
+        category: Option<DBFeedbackCategory>,
+        comment: Option<&str>,
+    ) -> Result<DBMessageFeedback, sqlx::Error> {
+        sqlx::query_as!(
+            DBMessageFeedback,
+            r#"
+            INSERT INTO chat.message_feedback (message_id, user_id, rating, category, comment)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (message_id, user_id) DO UPDATE
+            SET rating = EXCLUDED.rating,
+                category = EXCLUDED.category,
+                comment = EXCLUDED.comment,
+                updated_at = CURRENT_TIMESTAMP
+            RETURNING id, message_id, user_id, rating as "rating: _", category as "category: _",
+                comment, created_at, updated_at
+            "#,
+            message_id,
+            user_id,
+            rating as DBFeedbackRating,
+            category as Option<DBFeedbackCategory>,
+            comment
+        )
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// All feedback, newest first, joined with the rated answer, the question it answers and
+    /// the model and sources the answer was generated with.
+    pub async fn get_feedback_export(
+        &self,
+        rating: Option<DBFeedbackRating>,
+        category: Option<DBFeedbackCategory>,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<DBFeedbackExport>, sqlx::Error> {
+        sqlx::query_as!(
+            DBFeedbackExport,
+            r#"
+            SELECT f.id as feedback_id, f.rating as "rating: _", f.category as "category: _", f.comment,
+                f.updated_at as rated_at, f.user_id, answer.conversation_id, answer.id as message_id,
+                question.content as "prompt?", answer.content as answer, answer.model, answer.sources
+            FROM chat.message_feedback f
+            JOIN chat.messages answer ON answer.id = f.message_id
+            LEFT JOIN chat.messages question ON question.id = answer.parent_id
+            WHERE ($1::chat.feedback_rating IS NULL OR f.rating = $1)
+              AND ($2::chat.feedback_category IS NULL OR f.category = $2)
+              AND ($3::timestamptz IS NULL OR f.updated_at >= $3)
+            ORDER BY f.updated_at DESC, f.id DESC
+            "#,
+            rating as Option<DBFeedbackRating>,
+            category as Option<DBFeedbackCategory>,
+            since
         )
             .fetch_all(&self.pool)
             .await
@@ -1,19 +1,29 @@
 use crate::storage::vector::{
-    VectorDataPoint, VectorStorage, VectorStorageError, VectorStorageQueryResponse,
-    VectorStorageResponse,
+    SearchMode, VectorDataPoint, VectorQuery, VectorQueryOptions, VectorStorage,
+    VectorStorageError, VectorStorageQueryResponse, VectorStorageResponse,
 };
 use async_trait::async_trait;
 use fastembed::{Embedding, EmbeddingModel, TextEmbedding};
 use qdrant_client::qdrant::point_id::PointIdOptions;
 use qdrant_client::qdrant::value::Kind;
-use qdrant_client::qdrant::Value;
+use qdrant_client::qdrant::vector_output::Vector as VectorOutputKind;
+use qdrant_client::qdrant::vectors_output::VectorsOptions;
+use qdrant_client::qdrant::{Value, VectorsOutput};
 use qdrant_client::qdrant::{
-    CreateCollectionBuilder, Distance, PointStruct, QueryPointsBuilder, ScalarQuantizationBuilder,
-    ScoredPoint, UpsertPointsBuilder, VectorParamsBuilder, Vectors,
+    CreateCollectionBuilder, Distance, Fusion, Modifier, PointStruct, PrefetchQueryBuilder, Query,
+    QueryPointsBuilder, ScalarQuantizationBuilder, ScoredPoint, SparseVectorParamsBuilder,
+    SparseVectorsConfigBuilder, UpsertPointsBuilder, Vector, VectorInput, VectorParamsBuilder,
+    Vectors, VectorsConfigBuilder,
 };
 use qdrant_client::Qdrant;
 use std::collections::HashMap;
 
+// names of the vectors of a point in hybrid collections, dense collections use one unnamed vector
+const DENSE_VECTOR: &str = "dense";
+const SPARSE_VECTOR: &str = "sparse";
+// every search of a hybrid query fetches this many times the requested points before fusion
+const HYBRID_PREFETCH_FACTOR: u64 = 2;
+
 pub struct QdrantAdapter {
     client: Qdrant,
 }
@@ -24,7 +34,7 @@ impl QdrantAdapter {
             Ok(client) => Ok(Self { client }),
             Err(err) => Err(VectorStorageError::Message(format!(
                 "Could not create instance of Qdrant client: {}",
-                err.to_string()
+                err
             ))),
         }
     }
@@ -34,7 +44,7 @@ impl QdrantAdapter {
             Ok(client) => Ok(Self { client }),
             Err(err) => Err(VectorStorageError::Message(format!(
                 "Could not create instance of Qdrant client: {}",
-                err.to_string()
+                err
             ))),
         }
     }
@@ -42,30 +52,46 @@ impl QdrantAdapter {
 
 #[async_trait]
 impl VectorStorage for QdrantAdapter {
-    // Qdrant collections default to Cosine similarity and quantization
+    // Qdrant collections default to Cosine similarity and quantization, hybrid collections add a
+    // sparse vector weighted by inverse document frequency
     async fn create_collection(
         &self,
         collection_name: &str,
         embedding_model: EmbeddingModel,
+        mode: SearchMode,
     ) -> Result<VectorStorageResponse, VectorStorageError> {
         let dim = match TextEmbedding::get_model_info(&embedding_model) {
             Ok(model_info) => model_info.dim,
             Err(err) => {
                 return Err(VectorStorageError::Message(format!(
                     "Could not determine dimension of Embedding Model output: {}",
-                    err.to_string()
+                    err
                 )))
             }
         };
 
-        let response = self
-            .client
-            .create_collection(
-                CreateCollectionBuilder::new(collection_name)
-                    .vectors_config(VectorParamsBuilder::new(dim as u64, Distance::Cosine))
-                    .quantization_config(ScalarQuantizationBuilder::default()),
-            )
-            .await;
+        let dense = VectorParamsBuilder::new(dim as u64, Distance::Cosine);
+        let request = CreateCollectionBuilder::new(collection_name)
+            .quantization_config(ScalarQuantizationBuilder::default());
+
+        let request = match mode {
+            SearchMode::Dense => request.vectors_config(dense),
+            SearchMode::Hybrid => {
+                let mut vectors = VectorsConfigBuilder::default();
+                vectors.add_named_vector_params(DENSE_VECTOR, dense);
+                let mut sparse_vectors = SparseVectorsConfigBuilder::default();
+                sparse_vectors.add_named_vector_params(
+                    SPARSE_VECTOR,
+                    SparseVectorParamsBuilder::default().modifier(Modifier::Idf as i32),
+                );
+
+                request
+                    .vectors_config(vectors)
+                    .sparse_vectors_config(sparse_vectors)
+            }
+        };
+
+        let response = self.client.create_collection(request).await;
 
         match response {
             Ok(collection_operation_response) => Ok(VectorStorageResponse {
@@ -74,7 +100,7 @@ impl VectorStorage for QdrantAdapter {
 
             Err(e) => Err(VectorStorageError::Message(format!(
                 "Unable to create Qdrant collection: {}",
-                e.to_string()
+                e
             ))),
         }
     }
@@ -82,19 +108,60 @@ impl VectorStorage for QdrantAdapter {
     async fn query(
         &self,
         collection_name: &str,
-        vector: Embedding,
+        query: VectorQuery,
+        options: &VectorQueryOptions,
     ) -> Result<VectorStorageQueryResponse, VectorStorageError> {
-        match self
-            .client
-            .query(
-                QueryPointsBuilder::new(collection_name)
-                    .query(vector)
-                    .with_payload(true)
-                    .with_vectors(true)
-                    .limit(5),
-            )
-            .await
-        {
+        let request = QueryPointsBuilder::new(collection_name)
+            .with_payload(true)
+            .with_vectors(true)
+            .limit(options.limit)
+            .offset(options.offset);
+
+        let request = match options.mode {
+            SearchMode::Dense => {
+                let mut request = request.query(query.dense);
+                if let Some(threshold) = options.score_threshold {
+                    request = request.score_threshold(threshold);
+                }
+                request
+            }
+            // reciprocal rank fusion of the dense and the sparse search, both are filtered by their
+            // own threshold because the fused scores only reflect ranks
+            SearchMode::Hybrid => {
+                let sparse = query.sparse.ok_or_else(|| {
+                    VectorStorageError::Message(format!(
+                        "Hybrid query of {} is missing the sparse vector",
+                        collection_name
+                    ))
+                })?;
+                let candidates = (options.limit + options.offset) * HYBRID_PREFETCH_FACTOR;
+
+                let mut dense = PrefetchQueryBuilder::default()
+                    .query(Query::new_nearest(query.dense))
+                    .using(DENSE_VECTOR)
+                    .limit(candidates);
+                if let Some(threshold) = options.score_threshold {
+                    dense = dense.score_threshold(threshold);
+                }
+                let mut sparse = PrefetchQueryBuilder::default()
+                    .query(Query::new_nearest(VectorInput::new_sparse(
+                        sparse.indices,
+                        sparse.values,
+                    )))
+                    .using(SPARSE_VECTOR)
+                    .limit(candidates);
+                if let Some(threshold) = options.sparse_score_threshold {
+                    sparse = sparse.score_threshold(threshold);
+                }
+
+                request
+                    .add_prefetch(dense)
+                    .add_prefetch(sparse)
+                    .query(Query::new_fusion(Fusion::Rrf))
+            }
+        };
+
+        match self.client.query(request).await {
             Ok(query_response) => {
                 let points: Result<Vec<VectorDataPoint>, VectorStorageError> = query_response
                     .result
@@ -109,7 +176,7 @@ impl VectorStorage for QdrantAdapter {
             }
             Err(e) => Err(VectorStorageError::Message(format!(
                 "Qdrant query failed: {}",
-                e.to_string()
+                e
             ))),
         }
     }
@@ -134,7 +201,7 @@ impl VectorStorage for QdrantAdapter {
             }),
             Err(e) => Err(VectorStorageError::Message(format!(
                 "Qdrant upsert vectors failed: {}",
-                e.to_string()
+                e
             ))),
         }
     }
@@ -192,7 +259,8 @@ impl VectorDataPoint {
             content,
             name,
             score: Some(scored_point.score),
-            embedding: None,
+            embedding: dense_vector(scored_point.vectors),
+            sparse_embedding: None,
         })
     }
 
@@ -210,6 +278,34 @@ impl VectorDataPoint {
         payload.insert("content".to_string(), self.content.into());
         payload.insert("name".to_string(), self.name.into());
 
-        Ok(PointStruct::new(self.uuid, Vectors::from(vectors), payload))
+        // points with a sparse vector belong to a hybrid collection and use named vectors
+        let vectors = match self.sparse_embedding {
+            Some(sparse) => Vectors::from(HashMap::from([
+                (DENSE_VECTOR.to_string(), Vector::new_dense(vectors)),
+                (
+                    SPARSE_VECTOR.to_string(),
+                    Vector::new_sparse(sparse.indices, sparse.values),
+                ),
+            ])),
+            None => Vectors::from(vectors),
+        };
+
+        Ok(PointStruct::new(self.uuid, vectors, payload))
+    }
+}
+
+// the stored dense vector of a query result, the unnamed one or 'dense' in hybrid collections
+fn dense_vector(vectors: Option<VectorsOutput>) -> Option<Embedding> {
+    let output = match vectors?.vectors_options? {
+        VectorsOptions::Vector(output) => output,
+        VectorsOptions::Vectors(mut named) => named.vectors.remove(DENSE_VECTOR)?,
+    };
+
+    match output.vector {
+        Some(VectorOutputKind::Dense(dense)) => Some(dense.data),
+        Some(_) => None,
+        // servers before 1.13 only fill the deprecated fields
+        None if output.indices.is_none() && output.vectors_count.is_none() => Some(output.data),
+        None => None,
     }
 }
@@ -1,6 +1,8 @@
 use crate::storage::qdrant::QdrantAdapter;
 use async_trait::async_trait;
 use fastembed::{Embedding, EmbeddingModel};
+use serde::Deserialize;
+use strum_macros::{Display, EnumString};
 use thiserror::Error;
 
 pub enum VectorStorageBackend {
@@ -8,16 +10,18 @@ pub enum VectorStorageBackend {
 }
 
 #[async_trait]
-pub trait VectorStorage {
+pub trait VectorStorage: Send + Sync {
     async fn create_collection(
         &self,
         collection_name: &str,
         embedding_model: EmbeddingModel,
+        mode: SearchMode,
     ) -> Result<VectorStorageResponse, VectorStorageError>;
     async fn query(
         &self,
         collection_name: &str,
-        vector: Embedding,
+        query: VectorQuery,
+        options: &VectorQueryOptions,
     ) -> Result<VectorStorageQueryResponse, VectorStorageError>;
     async fn add_vectors(
         &self,
@@ -35,6 +39,95 @@ pub fn build(
     }
 }
 
+/// How the points of a collection are stored and searched, chosen per collection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, EnumString, Display, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    // a single dense vector per point
+    #[default]
+    #[strum(serialize = "dense")]
+    Dense,
+    // a dense and a sparse vector per point, both are searched and the results fused by rank
+    #[strum(serialize = "hybrid")]
+    Hybrid,
+}
+
+/// Weights of the terms of a text by term index, indices are unique and ascending.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SparseVector {
+    pub indices: Vec<u32>,
+    pub values: Vec<f32>,
+}
+
+/// The vectors a collection is searched with, the sparse one is only used in hybrid mode.
+#[derive(Debug, Clone)]
+pub struct VectorQuery {
+    pub dense: Embedding,
+    pub sparse: Option<SparseVector>,
+}
+
+impl From<Embedding> for VectorQuery {
+    fn from(dense: Embedding) -> Self {
+        Self { dense, sparse: None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorQueryOptions {
+    // Maximum number of points returned (Default: 5)
+    pub limit: u64,
+    // Points with a lower similarity score are left out, in hybrid mode this applies to the dense
+    // search before fusion (Default: None, every point is returned)
+    pub score_threshold: Option<f32>,
+    // Points with a lower BM25 score are left out of the sparse search before fusion, only used in
+    // hybrid mode, BM25 scores are not bounded so this is separate from score_threshold
+    // (Default: None, every point is returned)
+    pub sparse_score_threshold: Option<f32>,
+    // Number of best points skipped, for paging through results (Default: 0)
+    pub offset: u64,
+    // Must match the mode the collection was created with (Default: Dense)
+    pub mode: SearchMode,
+}
+
+impl Default for VectorQueryOptions {
+    fn default() -> Self {
+        Self {
+            limit: 5,
+            score_threshold: None,
+            sparse_score_threshold: None,
+            offset: 0,
+            mode: SearchMode::Dense,
+        }
+    }
+}
+
+impl VectorQueryOptions {
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn score_threshold(mut self, threshold: f32) -> Self {
+        self.score_threshold = Some(threshold);
+        self
+    }
+
+    pub fn sparse_score_threshold(mut self, threshold: f32) -> Self {
+        self.sparse_score_threshold = Some(threshold);
+        self
+    }
+
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn mode(mut self, mode: SearchMode) -> Self {
+        self.mode = mode;
+        self
+    }
+}
+
 pub struct VectorStorageResponse {
     pub time: f64,
 }
@@ -53,6 +146,10 @@ pub struct VectorDataPoint {
     // maps to 'content' key in DB
     pub content: String,
     pub embedding: Option<Embedding>,
+    // only stored in hybrid collections
+    pub sparse_embedding: Option<SparseVector>,
+    // cosine similarity in dense mode, the reciprocal rank fusion score in hybrid mode, which only
+    // depends on the ranks of the point in both searches and is not comparable to similarities
     pub score: Option<f32>,
 }
 
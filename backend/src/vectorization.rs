@@ -1,4 +1,6 @@
 pub mod chunk;
 pub mod document;
 pub mod embedding;
+pub mod rerank;
+pub mod sparse;
 pub mod utils;
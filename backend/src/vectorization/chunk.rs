@@ -12,7 +12,7 @@ pub fn chunk(content: String) -> Result<Vec<String>, ChunkError> {
     let reg = Regex::new(REGEX).map_err(|e| {
         ChunkError::Message(format!(
             "Failed to initialize regex builder: {}",
-            e.to_string()
+            e
         ))
     })?;
 
@@ -20,7 +20,7 @@ pub fn chunk(content: String) -> Result<Vec<String>, ChunkError> {
         .split(&content)
         .map(|chunk| {
             chunk.map(|c| c.to_string()).map_err(|e| {
-                ChunkError::Message(format!("Failed to chunk string: {}", e.to_string()))
+                ChunkError::Message(format!("Failed to chunk string: {}", e))
             })
         })
         .filter(|result| match result {
@@ -33,7 +33,7 @@ pub fn chunk(content: String) -> Result<Vec<String>, ChunkError> {
         Ok(c) => Ok(c),
         Err(e) => Err(ChunkError::Message(format!(
             "Failed to chunk string: {}",
-            e.to_string()
+            e
         ))),
     }
 }
@@ -63,7 +63,7 @@ pub fn chunk_by_words(
     let num_full_chunks = if words.len() <= max_words_per_chunk {
         1
     } else {
-        1 + (words.len() - max_words_per_chunk + step_size - 1) / step_size
+        1 + (words.len() - max_words_per_chunk).div_ceil(step_size)
     };
 
     for i in 0..num_full_chunks {
@@ -101,7 +101,7 @@ mod tests {
 
         let result = chunk(markdown).unwrap();
         assert_eq!(
-            result.get(0).cloned().unwrap(),
+            result.first().cloned().unwrap(),
             "# Title 1\n## Subheading 1\nContent 1\n".to_string()
         )
     }
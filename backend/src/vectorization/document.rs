@@ -10,7 +10,7 @@ pub struct Document {
 impl Document {
     pub fn new(path: &Path) -> Result<Self, DocumentError> {
         let content = fs::read_to_string(path).map_err(|e| {
-            DocumentError::Message(format!("Unable to read file at path: {}", e.to_string()))
+            DocumentError::Message(format!("Unable to read file at path: {}", e))
         })?;
 
         let file_name = path
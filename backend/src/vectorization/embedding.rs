@@ -24,8 +24,7 @@ pub fn embed(str: String) -> Result<Embedding, EmbeddingError> {
         .embed(vec![str], Some(1))
         .map_err(|e| EmbeddingError::Message(format!("Failed to embed string: {}", e)))?;
 
-    embeddings
-        .get(0)
+    embeddings.first()
         .ok_or_else(|| EmbeddingError::Message("Failed to parse generated embedding".to_string()))
         .cloned()
 }
@@ -41,8 +40,37 @@ pub fn embed_batch(chunks: &[String]) -> Result<Vec<Embedding>, EmbeddingError>
     Ok(embeddings)
 }
 
+/// Cosine similarity of two embeddings, 0 when either has no length or they differ in size.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+
+    let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let norm = a.iter().map(|a| a * a).sum::<f32>().sqrt() * b.iter().map(|b| b * b).sum::<f32>().sqrt();
+
+    if norm == 0.0 {
+        0.0
+    } else {
+        dot / norm
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum EmbeddingError {
     #[error("EmbeddingError occurred: {0}")]
     Message(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::vectorization::embedding::cosine_similarity;
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 3.0]).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+    }
+}
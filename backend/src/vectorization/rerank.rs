@@ -0,0 +1,77 @@
+use crate::vectorization::embedding::EmbeddingError;
+use fastembed::{RerankInitOptions, RerankerModel, TextRerank};
+use once_cell::sync::OnceCell;
+use std::sync::Arc;
+
+static MODEL: OnceCell<Arc<TextRerank>> = OnceCell::new();
+
+/// How many candidates the vector search over-fetches and how many the reranker keeps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RerankOptions {
+    // Points fetched per vector search before reranking (Default: 20)
+    pub candidates: u64,
+    // Points kept after reranking, the best scores first (Default: 5)
+    pub top_n: usize,
+}
+
+impl Default for RerankOptions {
+    fn default() -> Self {
+        Self {
+            candidates: 20,
+            top_n: 5,
+        }
+    }
+}
+
+impl RerankOptions {
+    pub fn candidates(mut self, candidates: u64) -> Self {
+        self.candidates = candidates;
+        self
+    }
+
+    pub fn top_n(mut self, top_n: usize) -> Self {
+        self.top_n = top_n;
+        self
+    }
+}
+
+pub fn get_model() -> Result<Arc<TextRerank>, EmbeddingError> {
+    MODEL
+        .get_or_try_init(|| {
+            let model = TextRerank::try_new(RerankInitOptions::new(RerankerModel::BGERerankerBase))
+                .map_err(|e| {
+                    EmbeddingError::Message(format!("Failed to initialize reranker model: {}", e))
+                })?;
+            Ok(Arc::new(model))
+        })
+        .cloned()
+}
+
+/// Scores how well every document answers the query with a cross-encoder, the scores are in the
+/// order of `documents` and higher is more relevant.
+///
+/// bge-reranker-base reads at most 512 tokens of the query and a document together, the rest of a
+/// longer chunk is cut off and does not count towards its score. Blocks while the model runs, call
+/// it from `spawn_blocking` in async code.
+pub fn rerank(query: &str, documents: &[String]) -> Result<Vec<f32>, EmbeddingError> {
+    if documents.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let model = get_model()?;
+    let documents: Vec<&str> = documents.iter().map(String::as_str).collect();
+
+    let results = model
+        .rerank(query, documents, false, None)
+        .map_err(|e| EmbeddingError::Message(format!("Failed to rerank documents: {}", e)))?;
+
+    // results are sorted by score, put them back in document order
+    let mut scores = vec![f32::MIN; results.len()];
+    for result in results {
+        if let Some(score) = scores.get_mut(result.index) {
+            *score = result.score;
+        }
+    }
+
+    Ok(scores)
+}
@@ -0,0 +1,86 @@
+use crate::storage::vector::SparseVector;
+use std::collections::{BTreeMap, BTreeSet};
+
+// BM25 term frequency saturation and document length normalization, the IDF part is applied by
+// Qdrant at query time through the IDF modifier of the sparse vector
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+// average number of terms per chunk, chunks are cut at up to 1000 words
+const AVERAGE_LENGTH: f32 = 500.0;
+
+/// BM25 weights of the terms of a chunk, terms are hashed to their index so no vocabulary has to
+/// be stored. Exact tokens like course codes match where dense embeddings only get close.
+pub fn sparse_embed(text: &str) -> SparseVector {
+    let terms = terms(text);
+    let length = terms.len() as f32;
+
+    let mut frequencies: BTreeMap<u32, f32> = BTreeMap::new();
+    for term in &terms {
+        *frequencies.entry(term_index(term)).or_default() += 1.0;
+    }
+
+    let norm = K1 * (1.0 - B + B * length / AVERAGE_LENGTH);
+    let (indices, values) = frequencies
+        .into_iter()
+        .map(|(index, frequency)| (index, frequency * (K1 + 1.0) / (frequency + norm)))
+        .unzip();
+
+    SparseVector { indices, values }
+}
+
+/// The terms of a query, each weighted 1 so the score of a chunk is the sum of its BM25 weights.
+pub fn sparse_embed_query(text: &str) -> SparseVector {
+    let indices: Vec<u32> = terms(text)
+        .iter()
+        .map(|term| term_index(term))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    SparseVector {
+        values: vec![1.0; indices.len()],
+        indices,
+    }
+}
+
+// lowercase alphanumeric runs, 'COSC-30603' and 'cosc 30603' give the same terms
+fn terms(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// 32 bit FNV-1a, stable across builds unlike the std hasher
+fn term_index(term: &str) -> u32 {
+    term.bytes().fold(0x811c9dc5_u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vectorization::sparse::{sparse_embed, sparse_embed_query};
+
+    #[test]
+    fn test_sparse_embed() {
+        let chunk = sparse_embed("COSC 30603 Data Structures. Prerequisite: COSC 20803.");
+        // 'cosc' occurs twice and is stored once
+        assert_eq!(chunk.indices.len(), 6);
+        assert!(chunk.indices.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let query = sparse_embed_query("What is cosc-30603?");
+        assert_eq!(query.values, vec![1.0; 4]);
+        let shared = query
+            .indices
+            .iter()
+            .filter(|index| chunk.indices.contains(index))
+            .count();
+        assert_eq!(shared, 2);
+
+        // repeated terms weigh more, but saturate
+        let weight = |text: &str| sparse_embed(text).values[0];
+        assert!(weight("cosc cosc") > weight("cosc"));
+        assert!(weight("cosc cosc") < 2.0 * weight("cosc"));
+    }
+}
@@ -1,13 +1,16 @@
 use crate::vectorization::chunk::ChunkError;
-use crate::storage::vector::VectorDataPoint;
+use crate::vectorization::sparse::sparse_embed;
+use crate::storage::vector::{SearchMode, VectorDataPoint};
 use fastembed::Embedding;
 use uuid::Uuid;
 
-// Generates a vector of VectorDataPoints for use with vectordbs
+// Generates a vector of VectorDataPoints for use with vectordbs, points for hybrid collections
+// get a sparse vector as well
 pub fn compile_vectors(
     file_name: String,
     chunks: Vec<String>,
     embeddings: Vec<Embedding>,
+    mode: SearchMode,
 ) -> Result<Vec<VectorDataPoint>, ChunkError> {
     let mut vector_data_points: Vec<VectorDataPoint> = vec![];
 
@@ -26,6 +29,10 @@ pub fn compile_vectors(
             name,
             content: chunk.clone(),
             embedding: Some(embedding),
+            sparse_embedding: match mode {
+                SearchMode::Dense => None,
+                SearchMode::Hybrid => Some(sparse_embed(chunk)),
+            },
             score: None,
         })
     }
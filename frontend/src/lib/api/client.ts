@@ -1,4 +1,5 @@
 import type { Message } from '$lib/model/messages.svelte';
+import type { Conversation } from '$lib/model/conversations.svelte';
 import { API_CONFIG } from './config';
 
 export async function fetchUser(jwt: string) {
@@ -38,18 +39,29 @@ export async function fetchMessages(jwt: string, conversationId: number) {
 	}
 }
 
-// only returns active conversationsSvelte
+// only returns active conversations, conversations are paginated so next_cursor is followed until
+// every conversation is loaded
 export async function fetchConversations(jwt: string) {
 	try {
-		const response = await fetch(`${API_CONFIG.BASE_URL}/conversations`, {
-			method: 'GET',
-			headers: {
-				'Content-Type': 'application/json',
-				Authorization: `Bearer ${jwt}`
-			}
-		});
+		const conversations: Conversation[] = [];
+		let cursor: string | null = null;
+
+		do {
+			const query: string = cursor ? `?limit=100&cursor=${encodeURIComponent(cursor)}` : '?limit=100';
+			const response = await fetch(`${API_CONFIG.BASE_URL}/conversations${query}`, {
+				method: 'GET',
+				headers: {
+					'Content-Type': 'application/json',
+					Authorization: `Bearer ${jwt}`
+				}
+			});
+			const data = await response.json();
+
+			conversations.push(...data.conversations);
+			cursor = data.next_cursor;
+		} while (cursor);
 
-		return await response.json();
+		return { conversations };
 	} catch (error) {
 		console.error('Error:', error);
 		return null;
@@ -64,9 +76,8 @@ export async function createConversation(jwt: string) {
 				'Content-Type': 'application/json',
 				Authorization: `Bearer ${jwt}`
 			},
-			body: JSON.stringify({
-				title: 'Untitled'
-			})
+			// the server generates a title after the first answer
+			body: JSON.stringify({})
 		});
 
 		const data = await response.json();
@@ -134,57 +145,37 @@ export async function createCompletion(jwt: string, messages: Message[], univers
 	}
 }
 
-export async function createStreamingCompletion(
+// stores the message and answers it, the answer is stored once it is complete, the response is a stream of
+// server-sent events
+export async function createConversationCompletion(
 	authToken: string,
-	messages: Message[],
+	conversationId: number,
+	content: string,
 	collection: string
 ): Promise<Response> {
-	const response = await fetch(`${API_CONFIG.BASE_URL}/completions/stream`, {
-		method: 'POST',
-		headers: {
-			'Content-Type': 'application/json',
-			'Authorization': `Bearer ${authToken}`
-		},
-		body: JSON.stringify({
-			messages,
-			collection
-		})
-	});
-
-	if (!response.ok) {
-		throw new Error(`Server responded with ${response.status}`);
-	}
-
-	return response;
-}
-
-
-export async function createTitle(jwt: string, messages: Message[]) {
-	try {
-		const formattedMessages = messages.map((message) => ({
-			content: message.content,
-			role: message.role
-		}));
-
-		const response = await fetch(`${API_CONFIG.BASE_URL}/completions/title`, {
+	const response = await fetch(
+		`${API_CONFIG.BASE_URL}/conversations/${conversationId}/completions`,
+		{
 			method: 'POST',
 			headers: {
 				'Content-Type': 'application/json',
-				Authorization: `Bearer ${jwt}`
+				'Authorization': `Bearer ${authToken}`
 			},
 			body: JSON.stringify({
-				messages: formattedMessages,
+				content,
+				collection
 			})
-		});
+		}
+	);
 
-		const data = await response.json();
-		return data.content;
-	} catch (error) {
-		console.error('Error:', error);
-		return '';
+	if (!response.ok) {
+		throw new Error(`Server responded with ${response.status}`);
 	}
+
+	return response;
 }
 
+
 export async function updateUser(jwt: string, studentId: string, firstName: string, lastName: string, university: string): Promise<{ success: boolean; error?: string }> {
 	try {
 		const response = await fetch(`${API_CONFIG.BASE_URL}/users`, {
@@ -4,6 +4,7 @@ export type Conversation = {
 	owner_id: number;
 	status: string;
 	title: string;
+	title_generated: boolean;
 };
 
 export const conversations = $state({
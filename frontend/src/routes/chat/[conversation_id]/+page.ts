@@ -1,26 +1,37 @@
 import { error } from '@sveltejs/kit';
 import type { PageLoad } from './$types';
 import { API_CONFIG } from '$lib/api/config';
+import type { Message } from '$lib/model/messages.svelte';
 
 export const load: PageLoad = async ({ params, fetch, parent }) => {
 	const { authToken } = await parent();
 	const conversationId = params.conversation_id; // Get conversationId from URL params
 
 	try {
-		const response = await fetch(
-			`${API_CONFIG.BASE_URL}/conversations/${conversationId}/messages`,
-			{
-				method: 'GET',
-				headers: {
-					'Content-Type': 'application/json',
-					Authorization: `Bearer ${authToken}`
+		// messages are paginated, follow next_cursor until the whole conversation is loaded
+		const messages: Message[] = [];
+		let cursor: string | null = null;
+
+		do {
+			const query: string = cursor ? `?limit=100&cursor=${encodeURIComponent(cursor)}` : '?limit=100';
+			const response = await fetch(
+				`${API_CONFIG.BASE_URL}/conversations/${conversationId}/messages${query}`,
+				{
+					method: 'GET',
+					headers: {
+						'Content-Type': 'application/json',
+						Authorization: `Bearer ${authToken}`
+					}
 				}
-			}
-		);
-		const data = await response.json();
+			);
+			const data = await response.json();
+
+			messages.push(...data.messages);
+			cursor = data.next_cursor;
+		} while (cursor);
 
 		return {
-			messages: data.messages
+			messages
 		};
 	} catch (err) {
 		console.error('Error fetching messages:', err);